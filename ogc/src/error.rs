@@ -0,0 +1,158 @@
+//! The error type shared by the OGC clients and parsers.
+use crate::wms::ServiceExceptionReport;
+use std::fmt;
+
+/// A `Result` with this crate's `Error`
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Everything that can go wrong when talking to an OGC service or parsing its languages
+#[derive(Debug)]
+pub enum Error {
+  /// The request could not be sent or the response could not be read
+  Transport(reqwest::Error),
+  /// A file could not be read or written
+  Io(std::io::Error),
+  /// The browser's `fetch` failed, with the JavaScript error
+  Fetch(String),
+  /// The server answered with an unsuccessful HTTP status
+  Status { status: u16, body: String },
+  /// A document could not be decoded as XML, or not into the expected model
+  Xml(String),
+  /// A document could not be decoded as JSON
+  Json(serde_json::Error),
+  /// The server reported an OGC service exception
+  ServiceException(ServiceExceptionReport),
+  /// The response has a content type that cannot be handled for the request
+  UnsupportedContentType(String),
+  /// The operation is not supported by the service
+  OperationNotSupported(String),
+  /// The client and server have no protocol version in common
+  UnsupportedVersion { requested: String, answered: String },
+  /// The request parameters or service configuration are invalid
+  InvalidParameter(String),
+  /// The input is not valid in the language being parsed, e.g. CQL
+  Parse(String),
+  /// An image could not be decoded or encoded
+  #[cfg(feature = "image")]
+  Image(image::ImageError),
+  /// The map image does not have the requested width and height
+  #[cfg(feature = "image")]
+  ImageSize {
+    expected: (u32, u32),
+    actual: (u32, u32),
+  },
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Error::Transport(e) => write!(f, "Transport error: {}", e),
+      Error::Io(e) => write!(f, "I/O error: {}", e),
+      Error::Fetch(e) => write!(f, "Fetch error: {}", e),
+      Error::Status { status, body } => write!(f, "HTTP status {}: {}", status, body),
+      Error::Xml(e) => write!(f, "Invalid XML: {}", e),
+      Error::Json(e) => write!(f, "Invalid JSON: {}", e),
+      Error::ServiceException(report) => write!(f, "{}", report),
+      Error::UnsupportedContentType(ct) => write!(f, "Unsupported content type: {:?}", ct),
+      Error::OperationNotSupported(op) => write!(f, "Operation not supported: {}", op),
+      Error::UnsupportedVersion {
+        requested,
+        answered,
+      } => write!(
+        f,
+        "No supported version: requested {}, server answered {}",
+        requested, answered
+      ),
+      Error::InvalidParameter(msg) => write!(f, "Invalid parameter: {}", msg),
+      Error::Parse(msg) => write!(f, "Parse error: {}", msg),
+      #[cfg(feature = "image")]
+      Error::Image(e) => write!(f, "Image error: {}", e),
+      #[cfg(feature = "image")]
+      Error::ImageSize { expected, actual } => write!(
+        f,
+        "Expected a {}x{} image but got {}x{}",
+        expected.0, expected.1, actual.0, actual.1
+      ),
+    }
+  }
+}
+
+impl std::error::Error for Error {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Error::Transport(e) => Some(e),
+      Error::Io(e) => Some(e),
+      Error::Json(e) => Some(e),
+      Error::ServiceException(report) => Some(report),
+      #[cfg(feature = "image")]
+      Error::Image(e) => Some(e),
+      _ => None,
+    }
+  }
+}
+
+impl From<reqwest::Error> for Error {
+  fn from(e: reqwest::Error) -> Self {
+    Error::Transport(e)
+  }
+}
+
+impl From<std::io::Error> for Error {
+  fn from(e: std::io::Error) -> Self {
+    Error::Io(e)
+  }
+}
+
+impl From<serde_xml_rs::Error> for Error {
+  fn from(e: serde_xml_rs::Error) -> Self {
+    Error::Xml(e.to_string())
+  }
+}
+
+impl From<xml::reader::Error> for Error {
+  fn from(e: xml::reader::Error) -> Self {
+    Error::Xml(e.to_string())
+  }
+}
+
+impl From<xml::writer::Error> for Error {
+  fn from(e: xml::writer::Error) -> Self {
+    Error::Xml(e.to_string())
+  }
+}
+
+impl From<serde_json::Error> for Error {
+  fn from(e: serde_json::Error) -> Self {
+    Error::Json(e)
+  }
+}
+
+impl From<url::ParseError> for Error {
+  fn from(e: url::ParseError) -> Self {
+    Error::InvalidParameter(format!("Invalid URL: {}", e))
+  }
+}
+
+#[cfg(feature = "image")]
+impl From<image::ImageError> for Error {
+  fn from(e: image::ImageError) -> Self {
+    Error::Image(e)
+  }
+}
+
+impl From<ServiceExceptionReport> for Error {
+  fn from(report: ServiceExceptionReport) -> Self {
+    Error::ServiceException(report)
+  }
+}
+
+impl From<nom::Err<nom::error::Error<&str>>> for Error {
+  fn from(e: nom::Err<nom::error::Error<&str>>) -> Self {
+    match e {
+      nom::Err::Incomplete(_) => Error::Parse("Incomplete input".to_string()),
+      nom::Err::Error(e) | nom::Err::Failure(e) => {
+        Error::Parse(format!("{:?} at {:?}", e.code, e.input))
+      }
+    }
+  }
+}
@@ -0,0 +1,296 @@
+//! HTTP transport for the service clients.
+//!
+//! Services are generic over an `HttpClient` so that authentication, proxies and timeouts
+//! can be configured, and so that they can be tested without a live server:
+//! ```
+//! use ogc::http::{HttpResponse, MockClient};
+//! use ogc::wms::{WebMappingService, Wms};
+//! #[tokio::main]
+//! async fn main() {
+//!   let xml = std::fs::read("./examples/WMS-1.3.0.xml").unwrap();
+//!   let client = MockClient::default()
+//!     .with_response("GetCapabilities", HttpResponse::ok("text/xml", xml));
+//!   let mut wms =
+//!     WebMappingService::from_url_with_client("http://hostname/path".to_string(), client)
+//!       .unwrap();
+//!   let capa = wms.get_capabilities().await.unwrap();
+//!   assert_eq!(capa.service.title, "Acme Corp. Map Server");
+//! }
+//! ```
+use async_trait::async_trait;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use url::Url;
+
+/// The parts of an HTTP response used by the service clients
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HttpResponse {
+  pub status: u16,
+  /// The response headers, names in lower case
+  pub headers: Vec<(String, String)>,
+  pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+  /// A 200 response with the given content type and body
+  pub fn ok(content_type: &str, body: Vec<u8>) -> Self {
+    HttpResponse {
+      status: 200,
+      headers: vec![("content-type".to_string(), content_type.to_string())],
+      body,
+    }
+  }
+
+  /// The value of the first header with the given (case insensitive) name
+  pub fn header(&self, name: &str) -> Option<&str> {
+    self
+      .headers
+      .iter()
+      .find(|(k, _)| k.eq_ignore_ascii_case(name))
+      .map(|(_, v)| v.as_str())
+  }
+
+  /// The Content-Type header, if present
+  pub fn content_type(&self) -> Option<&str> {
+    self.header("content-type")
+  }
+
+  /// Whether the status is 2xx
+  pub fn is_success(&self) -> bool {
+    (200..300).contains(&self.status)
+  }
+
+  /// The body as text, replacing invalid UTF-8
+  pub fn text(&self) -> String {
+    String::from_utf8_lossy(&self.body).into_owned()
+  }
+}
+
+/// Transport for the requests made by the service clients
+#[async_trait(?Send)]
+pub trait HttpClient {
+  /// Perform an HTTP GET request, sending the given headers in addition to any the client
+  /// adds itself
+  async fn get(&self, url: &Url, headers: &[(String, String)]) -> crate::Result<HttpResponse>;
+}
+
+/// An `HttpClient` backed by reqwest. Proxies, timeouts and default headers (e.g. for
+/// authentication) are configured on the wrapped `reqwest::Client`.
+#[derive(Clone, Debug, Default)]
+pub struct ReqwestClient {
+  client: reqwest::Client,
+}
+
+impl ReqwestClient {
+  pub fn new(client: reqwest::Client) -> Self {
+    ReqwestClient { client }
+  }
+}
+
+#[async_trait(?Send)]
+impl HttpClient for ReqwestClient {
+  async fn get(&self, url: &Url, headers: &[(String, String)]) -> crate::Result<HttpResponse> {
+    let mut req = self.client.get(url.clone());
+    for (name, value) in headers {
+      req = req.header(name.as_str(), value.as_str());
+    }
+    let resp = req.send().await?;
+    let status = resp.status().as_u16();
+    let headers = resp
+      .headers()
+      .iter()
+      .filter_map(|(name, value)| {
+        value
+          .to_str()
+          .ok()
+          .map(|v| (name.as_str().to_string(), v.to_string()))
+      })
+      .collect();
+    let body = resp.bytes().await?.to_vec();
+    Ok(HttpResponse {
+      status,
+      headers,
+      body,
+    })
+  }
+}
+
+/// An in-memory `HttpClient` replaying recorded responses, for tests and offline use.
+///
+/// Responses are registered per WMS request type, i.e. the value of the `REQUEST` query
+/// parameter, and are replayed in the order they were added; the last one is repeated once
+/// the others have been used. Clones share their responses and request log.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MockClient {
+  responses: Rc<RefCell<HashMap<String, Vec<HttpResponse>>>>,
+  requests: Rc<RefCell<Vec<(Url, Headers)>>>,
+}
+
+type Headers = Vec<(String, String)>;
+
+impl MockClient {
+  /// Add a response for the given request type, e.g. `GetMap`
+  pub fn with_response(self, request: &str, response: HttpResponse) -> Self {
+    self
+      .responses
+      .borrow_mut()
+      .entry(request.to_lowercase())
+      .or_default()
+      .push(response);
+    self
+  }
+
+  /// The URLs requested so far, oldest first
+  pub fn requests(&self) -> Vec<Url> {
+    self
+      .requests
+      .borrow()
+      .iter()
+      .map(|(url, _)| url.clone())
+      .collect()
+  }
+
+  /// The headers sent with each request so far, oldest first
+  pub fn headers(&self) -> Vec<Vec<(String, String)>> {
+    self
+      .requests
+      .borrow()
+      .iter()
+      .map(|(_, headers)| headers.clone())
+      .collect()
+  }
+}
+
+#[async_trait(?Send)]
+impl HttpClient for MockClient {
+  async fn get(&self, url: &Url, headers: &[(String, String)]) -> crate::Result<HttpResponse> {
+    self
+      .requests
+      .borrow_mut()
+      .push((url.clone(), headers.to_vec()));
+    let request = url
+      .query_pairs()
+      .find(|(k, _)| k.eq_ignore_ascii_case("REQUEST"))
+      .map(|(_, v)| v.to_lowercase())
+      .unwrap_or_default();
+    let mut responses = self.responses.borrow_mut();
+    let queue = responses.get_mut(&request).filter(|q| !q.is_empty());
+    match queue {
+      Some(queue) if queue.len() > 1 => Ok(queue.remove(0)),
+      Some(queue) => Ok(queue[0].clone()),
+      None => Ok(HttpResponse {
+        status: 404,
+        headers: Vec::new(),
+        body: format!("No recorded response for {}", url).into_bytes(),
+      }),
+    }
+  }
+}
+
+/// The client used when none is given: the browser's `fetch` on wasm32, reqwest elsewhere
+#[cfg(not(target_arch = "wasm32"))]
+pub type DefaultClient = ReqwestClient;
+#[cfg(target_arch = "wasm32")]
+pub type DefaultClient = FetchClient;
+
+/// An `HttpClient` using the browser's `fetch`, from a window or from a worker such as a
+/// service worker (`ServiceWorkerGlobalScope`)
+#[cfg(target_arch = "wasm32")]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FetchClient {}
+
+#[cfg(target_arch = "wasm32")]
+#[async_trait(?Send)]
+impl HttpClient for FetchClient {
+  async fn get(&self, url: &Url, headers: &[(String, String)]) -> crate::Result<HttpResponse> {
+    use crate::Error;
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+
+    let js_error = |e: wasm_bindgen::JsValue| Error::Fetch(format!("{:?}", e));
+
+    let init = web_sys::RequestInit::new();
+    init.set_method("GET");
+    init.set_mode(web_sys::RequestMode::Cors);
+    let request = web_sys::Request::new_with_str_and_init(url.as_str(), &init).map_err(js_error)?;
+    for (name, value) in headers {
+      request.headers().set(name, value).map_err(js_error)?;
+    }
+
+    let global = js_sys::global();
+    let promise = if let Some(window) = global.dyn_ref::<web_sys::Window>() {
+      window.fetch_with_request(&request)
+    } else if let Some(worker) = global.dyn_ref::<web_sys::WorkerGlobalScope>() {
+      worker.fetch_with_request(&request)
+    } else {
+      return Err(Error::Fetch(
+        "No window or worker scope to fetch from".to_string(),
+      ));
+    };
+    let resp: web_sys::Response = JsFuture::from(promise)
+      .await
+      .map_err(js_error)?
+      .dyn_into()
+      .map_err(js_error)?;
+
+    let mut headers = Vec::new();
+    if let Ok(Some(entries)) = js_sys::try_iter(&resp.headers()) {
+      for entry in entries.flatten() {
+        let pair: js_sys::Array = entry.unchecked_into();
+        if let (Some(name), Some(value)) = (pair.get(0).as_string(), pair.get(1).as_string()) {
+          headers.push((name.to_lowercase(), value));
+        }
+      }
+    }
+    let buffer = JsFuture::from(resp.array_buffer().map_err(js_error)?)
+      .await
+      .map_err(js_error)?;
+    Ok(HttpResponse {
+      status: resp.status(),
+      headers,
+      body: js_sys::Uint8Array::new(&buffer).to_vec(),
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{HttpClient, HttpResponse, MockClient};
+  use url::Url;
+
+  #[tokio::test]
+  async fn test_mock_client_replay() {
+    let client = MockClient::default()
+      .with_response(
+        "GetCapabilities",
+        HttpResponse::ok("text/xml", b"a".to_vec()),
+      )
+      .with_response(
+        "GetCapabilities",
+        HttpResponse::ok("text/xml", b"b".to_vec()),
+      );
+    let url = Url::parse("http://hostname/path?request=GetCapabilities").unwrap();
+    let bodies = vec![
+      client.get(&url, &[]).await.unwrap().body,
+      client.get(&url, &[]).await.unwrap().body,
+      client.get(&url, &[]).await.unwrap().body,
+    ];
+    assert_eq!(bodies, vec![b"a".to_vec(), b"b".to_vec(), b"b".to_vec()]);
+
+    let other = Url::parse("http://hostname/path?REQUEST=GetMap").unwrap();
+    let resp = client.get(&other, &[]).await.unwrap();
+    assert_eq!(resp.status, 404);
+    assert!(!resp.is_success());
+    assert_eq!(client.requests().len(), 4);
+  }
+
+  #[test]
+  fn test_response_headers() {
+    let resp = HttpResponse::ok("image/png", Vec::new());
+    assert_eq!(resp.content_type(), Some("image/png"));
+    assert_eq!(resp.header("Content-Type"), Some("image/png"));
+    assert_eq!(resp.header("ETag"), None);
+    assert!(resp.is_success());
+  }
+}
@@ -6,6 +6,7 @@
 //! This version supports:
 //!  * WMS GetCapabilities
 //!  * WMS GetMap
+//!  * WMS GetFeatureInfo
 //!
 //! The planned order of implementation is
 //!  1. WMS
@@ -14,10 +15,13 @@
 #![allow(dead_code)]
 #[macro_use]
 extern crate serde_derive;
-extern crate anyhow;
 extern crate serde;
 extern crate serde_json;
 extern crate serde_xml_rs;
 
+mod error;
+pub mod http;
+pub mod parser;
 pub mod wms;
-pub mod parser;
\ No newline at end of file
+
+pub use error::{Error, Result};
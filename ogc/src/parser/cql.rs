@@ -12,7 +12,7 @@ use number::complete::{f32, float};
 use sequence::tuple;
 use serde::{Deserialize, Serialize,};
 
-use std::{any, convert::From, iter::FromIterator};
+use std::{any, convert::{From, TryFrom}, iter::FromIterator};
 
 use std::str;
 use wasm_bindgen::prelude::*;
@@ -84,28 +84,27 @@ pub enum ArithmeticOperator {
     PlusSign, MinusSign, Asterisk, Solidus
 }
 
-impl From<char> for ArithmeticOperator {
-    ///FIXME try_from
-    fn from(item: char) -> Self {
+impl TryFrom<char> for ArithmeticOperator {
+    type Error = crate::Error;
+
+    fn try_from(item: char) -> Result<Self, Self::Error> {
         match item {
-            '+' => ArithmeticOperator::PlusSign,
-            '-' => ArithmeticOperator::MinusSign,
-            '*' => ArithmeticOperator::Asterisk,
-            '/' => ArithmeticOperator::Solidus,
-            _   => panic!("Invalid input char"),
+            '+' => Ok(ArithmeticOperator::PlusSign),
+            '-' => Ok(ArithmeticOperator::MinusSign),
+            '*' => Ok(ArithmeticOperator::Asterisk),
+            '/' => Ok(ArithmeticOperator::Solidus),
+            _   => Err(crate::Error::Parse(format!("Invalid arithmetic operator: {:?}", item))),
         }
     }
 }
 
-impl From<&str> for ArithmeticOperator {
-    ///FIXME try_from
-    fn from(item: &str) -> Self {
-        match item.chars().nth(0).unwrap() {
-            '+' => ArithmeticOperator::PlusSign,
-            '-' => ArithmeticOperator::MinusSign,
-            '*' => ArithmeticOperator::Asterisk,
-            '/' => ArithmeticOperator::Solidus,
-            _   => panic!("Invalid input char"),
+impl TryFrom<&str> for ArithmeticOperator {
+    type Error = crate::Error;
+
+    fn try_from(item: &str) -> Result<Self, Self::Error> {
+        match item.chars().next() {
+            Some(c) => ArithmeticOperator::try_from(c),
+            None => Err(crate::Error::Parse("Empty arithmetic operator".to_string())),
         }
     }
 }
@@ -305,8 +304,8 @@ pub fn identifier<'a>(input: &'a str) -> IResult<&'a str, Identifier> {
 
 /// arithmeticOperator = plusSign | minusSign | asterisk | solidus;
 pub fn arithmetic_operator<'a>(input: &'a str) -> IResult<&'a str, ArithmeticOperator> {
-    map(alt((one_of("+-*/"), delimited(space0, one_of("+-*/"), space0))),
-     ArithmeticOperator::from)(input)
+    map_res(alt((one_of("+-*/"), delimited(space0, one_of("+-*/"), space0))),
+     ArithmeticOperator::try_from)(input)
 }
 
 /// arithmeticOperand = numericLiteral
@@ -337,6 +336,12 @@ pub fn arithmetic_expression<'a>(input: &'a str) -> IResult<&'a str, ArithmeticE
     |x| ArithmeticExpression { left_operand: x.0, operator: x.1, right_operand: x.2 })(input)
 }
 
+/// Parse a complete arithmetic expression, e.g. `speed * 10`, failing if any input is left over
+pub fn parse_arithmetic_expression(input: &str) -> crate::Result<ArithmeticExpression> {
+    let (_, expression) = all_consuming(arithmetic_expression)(input)?;
+    Ok(expression)
+}
+
 fn arithmetic_expression_spaced<'a>(input: &'a str) -> IResult<&'a str, (Operand, ArithmeticOperator, Operand)> {
     tuple((delimited(space0, arithmetic_operand, space0),
                      delimited(space0, arithmetic_operator, space0),
@@ -451,8 +456,9 @@ fn concat_values(x: Vec<Identifier>) -> String {
 #[cfg(test)]
 mod tests {
     use proptest::prelude::*;
+    use std::convert::TryFrom;
     use super::{Argument, ArithmeticExpression, ArithmeticOperator, Function, Identifier, NumericLiteral, Operand, Sign,
-         arithmetic_expression, arithmetic_expression_spaced, arithmetic_operand, arithmetic_operator, identifier, identifier_start, signed_numeric_literal};
+         arithmetic_expression, arithmetic_expression_spaced, arithmetic_operand, arithmetic_operator, identifier, identifier_start, parse_arithmetic_expression, signed_numeric_literal};
 
     proptest! {
 
@@ -493,12 +499,12 @@ mod tests {
         fn parse_arithmetic_operator(operator in "[-+*/]") {
             let r = arithmetic_operator(&operator).unwrap();
             assert_eq!(r.0, "");
-            assert_eq!(r.1, ArithmeticOperator::from(operator.chars().nth(0).unwrap()));
+            assert_eq!(r.1, ArithmeticOperator::try_from(operator.chars().nth(0).unwrap()).unwrap());
 
             let op2 = format!(" {} ", operator);
             let r2 = arithmetic_operator(&op2).unwrap();
             assert_eq!(r2.0, "");
-            assert_eq!(r2.1, ArithmeticOperator::from(operator.chars().nth(0).unwrap()));
+            assert_eq!(r2.1, ArithmeticOperator::try_from(operator.chars().nth(0).unwrap()).unwrap());
         }
 
         #[test]
@@ -508,7 +514,7 @@ mod tests {
             assert_eq!(r.0, "");
             assert_eq!(r.1.left_operand, Operand::identifier(Identifier::new(left_operand.clone(), false)));
             assert_eq!(r.1.right_operand, Operand::identifier(Identifier::new(right_operand.clone(), false)));
-            assert_eq!(r.1.operator, ArithmeticOperator::from(operator.clone().pop().unwrap()));
+            assert_eq!(r.1.operator, ArithmeticOperator::try_from(operator.clone().pop().unwrap()).unwrap());
 
             // Without whitespace
             let example2 = &format!("{}{}{}", left_operand, operator, right_operand);
@@ -516,7 +522,7 @@ mod tests {
             assert_eq!(r2.0, "");
             assert_eq!(r.1.left_operand, Operand::identifier(Identifier::new(left_operand.clone(), false)));
             assert_eq!(r.1.right_operand, Operand::identifier(Identifier::new(right_operand.clone(), false)));
-            assert_eq!(r2.1.operator, ArithmeticOperator::from(operator.clone().pop().unwrap()));
+            assert_eq!(r2.1.operator, ArithmeticOperator::try_from(operator.clone().pop().unwrap()).unwrap());
         }
 
         #[test]
@@ -527,7 +533,7 @@ mod tests {
             assert_eq!(r.0, "");
             assert_eq!(r.1.left_operand, Operand::identifier(Identifier::new(left_operand.clone(), false)));
             assert_eq!(r.1.right_operand, Operand::from(right_operand));
-            assert_eq!(r.1.operator, ArithmeticOperator::from(operator.clone().pop().unwrap()));
+            assert_eq!(r.1.operator, ArithmeticOperator::try_from(operator.clone().pop().unwrap()).unwrap());
 
             let example2 = &format!("{} {} {}", right_operand, operator, left_operand);
             println!("Example numeric 2 is {}", example2);
@@ -535,7 +541,7 @@ mod tests {
             assert_eq!(r2.0, "");
             assert_eq!(r2.1.right_operand, Operand::identifier(Identifier::new(left_operand.clone(), false)));
             assert_eq!(r2.1.left_operand, Operand::from(right_operand));
-            assert_eq!(r2.1.operator, ArithmeticOperator::from(operator.clone().pop().unwrap()));
+            assert_eq!(r2.1.operator, ArithmeticOperator::try_from(operator.clone().pop().unwrap()).unwrap());
         }
 
         #[test]
@@ -547,7 +553,7 @@ mod tests {
             assert_eq!(r.0, "");
             assert_eq!(r.1.0, Operand::identifier(Identifier::new(left_operand.clone(), false)));
             assert_eq!(r.1.2, Operand::from(right_operand));
-            assert_eq!(r.1.1, ArithmeticOperator::from(operator.clone().pop().unwrap()));
+            assert_eq!(r.1.1, ArithmeticOperator::try_from(operator.clone().pop().unwrap()).unwrap());
 
             let example2 = &format!("{} {} {}", right_operand, operator, left_operand);
             println!("Example is {}", example2);
@@ -555,7 +561,7 @@ mod tests {
             assert_eq!(r2.0, "");
             assert_eq!(r2.1.0, Operand::from(right_operand));
             assert_eq!(r2.1.2, Operand::identifier(Identifier::new(left_operand.clone(), false)));
-            assert_eq!(r2.1.1, ArithmeticOperator::from(operator.clone().pop().unwrap()));
+            assert_eq!(r2.1.1, ArithmeticOperator::try_from(operator.clone().pop().unwrap()).unwrap());
         }
 
         #[test]
@@ -565,7 +571,7 @@ mod tests {
             assert_eq!(r.0, "");
             assert_eq!(r.1.left_operand, Operand::identifier(Identifier::new(left_operand.clone(), false)));
             assert_eq!(r.1.right_operand, Operand::identifier(Identifier::new(right_operand.clone(), false)));
-            assert_eq!(r.1.operator, ArithmeticOperator::from(operator.clone().pop().unwrap()));
+            assert_eq!(r.1.operator, ArithmeticOperator::try_from(operator.clone().pop().unwrap()).unwrap());
         }
 
         #[test]
@@ -670,7 +676,16 @@ mod tests {
         let r = arithmetic_expression(example).unwrap();
         assert_eq!(r.0, "");
         assert_eq!(r.1.left_operand, Operand::identifier(Identifier::new("AAAAAAAaaa".to_string(), false)));
-        assert_eq!(r.1.operator, ArithmeticOperator::from("-"));
+        assert_eq!(r.1.operator, ArithmeticOperator::try_from("-").unwrap());
         assert_eq!(r.1.right_operand, Operand::literal(NumericLiteral { value: -f32::INFINITY, sign: Sign::Negative }));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parse_arithmetic_expression_errors() {
+        assert!(parse_arithmetic_expression("speed + delay").is_ok());
+        assert!(matches!(parse_arithmetic_expression("speed + delay )"), Err(crate::Error::Parse(_))));
+        assert!(matches!(parse_arithmetic_expression("+"), Err(crate::Error::Parse(_))));
+        assert!(matches!(ArithmeticOperator::try_from('%'), Err(crate::Error::Parse(_))));
+        assert!(matches!(ArithmeticOperator::try_from(""), Err(crate::Error::Parse(_))));
+    }
+}
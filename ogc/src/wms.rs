@@ -15,15 +15,31 @@
 //!   Ok(())
 //! }
 //! ```
+//! On wasm32 requests are sent with the browser's `fetch`, from a page or a service worker,
+//! and the client is exported to JavaScript as `WebMappingService` (see `JsWebMappingService`).
+//!
+//! Capabilities can be written back out with `GetCapabilities::to_xml`, e.g. to republish
+//! them with rewritten URLs or a subset of the layers, and converted to another version
+//! with `GetCapabilities::to_version`.
+//!
 //! ## WMS GetMap Support
 //! The supported request parameters are:
 //!  * VERSION
 //!  * LAYERS
 //!  * STYLES
-//!  * SRS
+//!  * SRS (1.1.x) or CRS (1.3.0)
+//!  * BBOX, in latitude/longitude order for geographic CRSs in 1.3.0
 //!  * WIDTH
 //!  * HEIGHT
 //!  * FORMAT
+//!  * TRANSPARENT, BGCOLOR, EXCEPTIONS, TIME and ELEVATION
+//!  * SLD, SLD_BODY and SLD_VERSION, from the Styled Layer Descriptor profile
+//!  * vendor specific parameters, e.g. `CQL_FILTER`
+//!
+//! `GetMapParameters::builder` keeps LAYERS and STYLES in step. Maps larger than the service
+//! allows are split into a grid of requests by `WebMappingService::tiles`, and with the
+//! `image` feature `WebMappingService::get_map_tiled` fetches and stitches them together,
+//! and `WebMappingService::get_map_image` decodes a map into a `MapImage`.
 //!
 //! e.g.:
 //! ```
@@ -43,6 +59,7 @@
 //!           miny: -90.0,
 //!           maxx: 180.0,
 //!           maxy: 90.0,
+//!           ..BoundingBox::default()
 //!       },
 //!       ..GetMapParameters::default()
 //!     }).await.unwrap();
@@ -50,33 +67,74 @@
 //!   let mut file = File::create("/tmp/terrestris-get-map.png").unwrap();
 //!   assert!(file.write_all(&bytes).is_ok());
 //! }
-use anyhow::Context;
+use crate::error::Error;
+use crate::http::{DefaultClient, HttpClient};
 use async_trait::async_trait;
 use serde_xml_rs::from_reader;
-use std::collections::HashSet;
+use std::collections::{HashSet, VecDeque};
+use std::ops::RangeInclusive;
 use url::Url;
 
+mod cache;
+mod conversion;
+mod dimension;
+mod exception;
+mod feature_info;
+mod iso8601;
+#[cfg(target_arch = "wasm32")]
+mod js;
+#[cfg(feature = "image")]
+mod raster;
+mod scale;
+mod tiling;
+mod validation;
+mod version;
+mod writer;
+
+pub use cache::CachedCapabilities;
+use dimension::Extent;
+pub use dimension::{Dimension, ExtentValue, MAX_EXTENT_VALUES};
+pub use exception::{ExceptionCode, ServiceException, ServiceExceptionReport};
+pub use feature_info::Feature;
+#[cfg(target_arch = "wasm32")]
+pub use js::JsWebMappingService;
+#[cfg(feature = "image")]
+pub use raster::MapImage;
+pub use scale::{scale_denominator, STANDARD_PIXEL_SIZE};
+pub use tiling::{tile_grid, Tile, DEFAULT_TILE_SIZE};
+pub use validation::Violation;
+use version::Negotiation;
+pub use version::SUPPORTED_VERSIONS;
+
 /// Behaviour for a Web Mapping Service endpoint as per the specification.
 #[async_trait(?Send)]
 pub trait Wms {
   /// The GetCapabilities request
-  async fn get_capabilities(&mut self) -> anyhow::Result<GetCapabilities>;
+  async fn get_capabilities(&mut self) -> crate::Result<GetCapabilities>;
 
   /// Optionally supported by a WMS endpoint
-  async fn get_feature_info(&mut self) -> anyhow::Result<GetFeatureInfo> {
-    Err(anyhow::Error::msg("Not supported"))
+  async fn get_feature_info(
+    &mut self,
+    _req: GetFeatureInfoParameters,
+  ) -> crate::Result<GetFeatureInfo> {
+    Err(Error::OperationNotSupported("GetFeatureInfo".to_string()))
   }
 
   /// Perform the GetMap request against the configured endpoint
-  async fn get_map(&mut self, req: GetMapParameters) -> anyhow::Result<Vec<u8>>;
+  async fn get_map(&mut self, req: GetMapParameters) -> crate::Result<Vec<u8>>;
 }
 
-/// A configurable WMS endpoint
+/// A configurable WMS endpoint, sending its requests through the HTTP client `C`
 #[derive(Clone, Debug, Default, PartialEq)]
-pub struct WebMappingService {
+pub struct WebMappingService<C = DefaultClient> {
+  /// The version proposed to the server, replaced by the negotiated version once the
+  /// capabilities have been loaded
   pub version: String,
   url: Option<Url>,
   raw_xml: Option<String>,
+  capabilities: Option<GetCapabilities>,
+  cache: Option<CachedCapabilities>,
+  client: C,
 }
 
 impl WebMappingService {
@@ -86,111 +144,322 @@ impl WebMappingService {
       version: "1.3.0".to_string(),
       url: None,
       raw_xml: Some(xml),
+      capabilities: None,
+      cache: None,
+      client: DefaultClient::default(),
     }
   }
 
   /// Use the given URL as the endpoint for service calls
   /// The URL should be the base URL for a WMS Service. Request parameters essential for
-  /// WMS requests will be replaced accordingly.
-  pub fn from_url(url: String) -> anyhow::Result<Self> {
+  /// WMS requests will be replaced accordingly. A VERSION parameter, if present, is used as
+  /// the version proposed during negotiation instead of the highest supported one.
+  pub fn from_url(url: String) -> crate::Result<Self> {
+    Self::from_url_with_client(url, DefaultClient::default())
+  }
+}
+
+impl<C: HttpClient> WebMappingService<C> {
+  /// Like `from_url`, sending the requests through the given HTTP client, e.g. one adding
+  /// authentication headers or a `MockClient` replaying recorded responses
+  pub fn from_url_with_client(url: String, client: C) -> crate::Result<Self> {
     let mut url = Url::parse(&url)?;
+    let mut version = SUPPORTED_VERSIONS[0].to_string();
+    let mut pairs = Vec::new();
+    for (k, v) in url.query_pairs().into_owned() {
+      if k.eq_ignore_ascii_case("VERSION") {
+        version = v;
+      } else if !k.eq_ignore_ascii_case("REQUEST") && !k.eq_ignore_ascii_case("SERVICE") {
+        pairs.push((k, v));
+      }
+    }
     url
       .query_pairs_mut()
+      .clear()
+      .extend_pairs(pairs)
       .append_pair("REQUEST", "GetCapabilities")
       .append_pair("SERVICE", "WMS");
     Ok(WebMappingService {
-      version: "1.3.0".to_string(),
+      version,
       url: Some(url),
       raw_xml: None,
+      capabilities: None,
+      cache: None,
+      client,
     })
   }
-}
 
-#[async_trait(?Send)]
-impl Wms for WebMappingService {
-  /// The WMS GetCapabilities request
-  async fn get_capabilities(&mut self) -> anyhow::Result<GetCapabilities> {
-    match &self.raw_xml {
-      None => match reqwest::get(self.url.clone().unwrap()).await?.text().await {
-        Ok(xml) => {
-          self.raw_xml = Some(xml);
-          self.get_capabilities().await
-        }
-        Err(e) => Err(anyhow::Error::msg(e)),
-      },
-      Some(xml) => match from_reader(xml.as_bytes()) {
-        Ok(w) => Ok(w),
-        Err(e) => Err(anyhow::Error::msg(e)),
-      },
+  /// Use a previously fetched capabilities document, e.g. one loaded with
+  /// `CachedCapabilities::load`, revalidating it with the server once it is stale. A
+  /// document fetched from another URL is ignored.
+  pub fn with_cache(mut self, cache: CachedCapabilities) -> Self {
+    if self.url.as_ref().map(Url::as_str) == Some(cache.url.as_str()) {
+      self.version = cache.version.clone();
+      self.cache = Some(cache);
     }
+    self
+  }
+
+  /// The capabilities document last fetched or revalidated, e.g. to save it for later runs
+  pub fn cached_capabilities(&self) -> Option<&CachedCapabilities> {
+    self.cache.as_ref()
+  }
+
+  /// Load the capabilities again, revalidating the cached document even if it is fresh
+  pub async fn refresh_capabilities(&mut self) -> crate::Result<GetCapabilities> {
+    let xml = self.fetch_capabilities(true).await?;
+    self.load_capabilities(xml)
+  }
+
+  /// Build the GetMap request URL for the given parameters
+  ///
+  /// Once the capabilities have been loaded the negotiated version replaces `req.version`.
+  pub fn get_map_url(&self, req: &GetMapParameters) -> crate::Result<Url> {
+    let mut url = self.operation_url(|r| Some(&r.get_map))?;
+    req.check()?;
+    url.query_pairs_mut().append_pair("REQUEST", "GetMap");
+    self.negotiated(req).append_query_pairs(&mut url);
+    Ok(url)
   }
 
-  async fn get_map(&mut self, req: GetMapParameters) -> anyhow::Result<Vec<u8>> {
-    let mut url = self.url.clone().unwrap();
+  /// Build the GetFeatureInfo request URL for the given parameters
+  ///
+  /// Once the capabilities have been loaded the negotiated version replaces `req.map.version`.
+  pub fn get_feature_info_url(&self, req: &GetFeatureInfoParameters) -> crate::Result<Url> {
+    let mut url = self.operation_url(|r| r.get_feature_info.as_ref())?;
+    req.map.check()?;
     url
       .query_pairs_mut()
-      .clear()
-      .append_pair("REQUEST", "GetMap")
-      .append_pair("VERSION", &req.version)
-      .append_pair("LAYERS", &req.layers_to_csv())
-      .append_pair("STYLES", &req.styles_to_csv())
-      .append_pair("SRS", &req.srs)
-      .append_pair("CRS", &req.srs)
-      .append_pair("BBOX", &req.bbox.to_str())
-      .append_pair("WIDTH", &req.width.to_string())
-      .append_pair("HEIGHT", &req.height.to_string())
-      .append_pair("FORMAT", &req.format)
-      .append_pair(
-        "TRANSPARENT",
-        &req.transparent.unwrap_or(true).to_string().to_uppercase(),
-      )
-      .append_pair("BG_COLOR", &req.bg_color.unwrap_or("".to_string()))
-      .append_pair("EXCEPTIONS", &req.exceptions.unwrap_or("".to_string()))
-      .append_pair("TIME", &req.time.unwrap_or("".to_string()))
-      .append_pair("ELEVATION", &req.elevation.unwrap_or("".to_string()));
-
-    let resp = reqwest::get(url).await?;
-    match resp.status() {
-      reqwest::StatusCode::OK => {
-        if let Some(ct_type) = resp.headers().get("Content-Type") {
-          if ct_type.to_str().unwrap().starts_with("image") {
-            resp
-              .bytes()
-              .await
-              .ok()
-              .map(|b| b.to_vec())
-              .context("Failed to stream image data")
-          } else if ct_type.to_str().unwrap().contains("/xml") {
-            Err(anyhow::Error::msg(format!(
-              "Exception response for GetMap: {:?}",
-              resp.text().await?
-            )))
-          } else {
-            Err(anyhow::Error::msg(format!(
-              "Unsupported content type: {:?}",
-              ct_type
-            )))
-          }
-        } else {
-          // Best guess...
-          // resp
-          //   .bytes()
-          //   .await
-          //   .ok()
-          //   .context("Failed to stream bytes for GetMap response")
-          panic!("TODO2");
+      .append_pair("REQUEST", "GetFeatureInfo");
+    let req = GetFeatureInfoParameters {
+      map: self.negotiated(&req.map),
+      ..req.clone()
+    };
+    req.map.append_query_pairs(&mut url);
+    req.append_query_pairs(&mut url);
+    Ok(url)
+  }
+
+  /// Send the GetMap request, returning the image bytes
+  async fn fetch_map(&self, req: &GetMapParameters) -> crate::Result<Vec<u8>> {
+    let url = self.get_map_url(req)?;
+    let resp = self.client.get(&url, &[]).await?;
+    if !resp.is_success() {
+      return Err(exception_response(resp.status, resp.text()));
+    }
+    let content_type = resp.content_type().unwrap_or_default().to_string();
+    let bytes = resp.body;
+    // Without a content type, fall back to recognising the image by its signature
+    if content_type.starts_with("image") || (content_type.is_empty() && is_image(&bytes)) {
+      Ok(bytes)
+    } else {
+      match ServiceExceptionReport::from_xml(&String::from_utf8_lossy(&bytes)) {
+        Some(report) => Err(report.into()),
+        None => Err(Error::UnsupportedContentType(content_type)),
+      }
+    }
+  }
+
+  /// The GetMap parameters with the negotiated version, if negotiation has taken place
+  fn negotiated(&self, req: &GetMapParameters) -> GetMapParameters {
+    let mut req = req.clone();
+    if self.capabilities.is_some() {
+      req.version = self.version.clone();
+    }
+    req
+  }
+
+  /// Fetch the capabilities document, negotiating the version with the server. A cached
+  /// document is used while fresh, unless `revalidate` is set, and is otherwise revalidated.
+  async fn fetch_capabilities(&mut self, revalidate: bool) -> crate::Result<String> {
+    if let Some(cache) = self.cache.as_ref().filter(|c| !revalidate && c.is_fresh()) {
+      self.version = cache.version.clone();
+      return Ok(cache.xml.clone());
+    }
+    let base = self.url.clone().ok_or_else(no_url)?;
+    let mut requested = self.version.clone();
+    loop {
+      let mut url = base.clone();
+      url.query_pairs_mut().append_pair("VERSION", &requested);
+      // The cached document is only replaced once the server confirms or replaces it
+      let cache = self.cache.as_ref().filter(|c| c.version == requested);
+      let mut headers = Vec::new();
+      if let Some(cache) = &cache {
+        if let Some(sequence) = &cache.update_sequence {
+          url
+            .query_pairs_mut()
+            .append_pair("UPDATESEQUENCE", sequence);
+        }
+        headers = cache.validators();
+      }
+      let resp = self.client.get(&url, &headers).await?;
+      let xml = resp.text();
+      if let Some(cache) = cache {
+        let current = resp.status == 304
+          || ServiceExceptionReport::from_xml(&xml)
+            .map_or(false, |r| r.has_code(&ExceptionCode::CurrentUpdateSequence));
+        if current {
+          let xml = cache.xml.clone();
+          self.version = cache.version.clone();
+          self.cache = cache.clone().revalidated(&resp);
+          return Ok(xml);
+        }
+      }
+      if !resp.is_success() {
+        return Err(exception_response(resp.status, xml));
+      }
+      if let Some(report) = ServiceExceptionReport::from_xml(&xml) {
+        return Err(report.into());
+      }
+      let answered = version::capabilities_version(&xml).unwrap_or_default();
+      match version::negotiate(&requested, &answered) {
+        Negotiation::Accept(v) => {
+          self.version = v.clone();
+          self.cache = CachedCapabilities::new(base.to_string(), v, xml.clone(), &resp);
+          return Ok(xml);
+        }
+        Negotiation::Retry(v) => requested = v.to_string(),
+        Negotiation::Fail => {
+          return Err(Error::UnsupportedVersion {
+            requested,
+            answered,
+          })
         }
       }
-      _ => {
-        let excep_xml = resp.text().await.ok().context("Couldn't stream text")?;
-        Err(anyhow::Error::msg(excep_xml))
+    }
+  }
+
+  /// Keep the capabilities document and parse it, taking the negotiated version from it
+  fn load_capabilities(&mut self, xml: String) -> crate::Result<GetCapabilities> {
+    let xml: &str = self.raw_xml.insert(xml);
+    if let Some(report) = ServiceExceptionReport::from_xml(xml) {
+      return Err(report.into());
+    }
+    let w: GetCapabilities = from_reader(xml.as_bytes())?;
+    if !w.version.is_empty() {
+      self.version = w.version.clone();
+    }
+    self.capabilities = Some(w.clone());
+    Ok(w)
+  }
+
+  /// The URL an operation should be sent to: the HTTP GET OnlineResource advertised for it in
+  /// the loaded capabilities, or the base URL without its query when none is advertised.
+  fn operation_url<F>(&self, operation: F) -> crate::Result<Url>
+  where
+    F: Fn(&Request) -> Option<&Operation>,
+  {
+    let advertised = self
+      .capabilities
+      .as_ref()
+      .and_then(|c| operation(&c.capability.request))
+      .and_then(Operation::get_url)
+      .and_then(|href| Url::parse(href).ok());
+    match advertised {
+      Some(mut url) => {
+        // The advertised URL is a prefix which may carry its own parameters, e.g. MapServer's
+        // `map`, so keep those but drop any dangling separators and the ones we always send
+        let pairs: Vec<(String, String)> = url
+          .query_pairs()
+          .into_owned()
+          .filter(|(k, _)| !k.eq_ignore_ascii_case("SERVICE") && !k.eq_ignore_ascii_case("REQUEST"))
+          .collect();
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+        Ok(url)
+      }
+      None => {
+        let mut url = self.url.clone().ok_or_else(no_url)?;
+        url.query_pairs_mut().clear();
+        Ok(url)
       }
     }
   }
 }
 
+#[async_trait(?Send)]
+impl<C: HttpClient> Wms for WebMappingService<C> {
+  /// The WMS GetCapabilities request
+  async fn get_capabilities(&mut self) -> crate::Result<GetCapabilities> {
+    // A document from the server is reused while its cache is fresh, and revalidated
+    // once it is stale
+    let fresh = self.url.is_none()
+      || self
+        .cache
+        .as_ref()
+        .map_or(false, CachedCapabilities::is_fresh);
+    let xml = match &self.raw_xml {
+      Some(xml) if fresh => xml.clone(),
+      _ => self.fetch_capabilities(false).await?,
+    };
+    self.load_capabilities(xml)
+  }
+
+  async fn get_map(&mut self, req: GetMapParameters) -> crate::Result<Vec<u8>> {
+    self.fetch_map(&req).await
+  }
+
+  async fn get_feature_info(
+    &mut self,
+    req: GetFeatureInfoParameters,
+  ) -> crate::Result<GetFeatureInfo> {
+    let url = self.get_feature_info_url(&req)?;
+    let resp = self.client.get(&url, &[]).await?;
+    if !resp.is_success() {
+      return Err(exception_response(resp.status, resp.text()));
+    }
+    let format = resp.content_type().unwrap_or(&req.info_format).to_string();
+    let body = resp.text();
+    match ServiceExceptionReport::from_xml(&body) {
+      Some(report) => Err(report.into()),
+      None => GetFeatureInfo::decode(&format, body),
+    }
+  }
+}
+
+fn no_url() -> Error {
+  Error::InvalidParameter("No URL configured for this service".to_string())
+}
+
+/// The error for an unsuccessful response: the service exception when the body is a
+/// `ServiceExceptionReport`, otherwise the HTTP status
+fn exception_response(status: u16, body: String) -> Error {
+  match ServiceExceptionReport::from_xml(&body) {
+    Some(report) => report.into(),
+    None => Error::Status { status, body },
+  }
+}
+
+/// Whether the bytes start with the signature of a common raster format
+fn is_image(bytes: &[u8]) -> bool {
+  const SIGNATURES: [&[u8]; 5] = [b"\x89PNG", b"\xFF\xD8\xFF", b"GIF8", b"II*\0", b"MM\0*"];
+  SIGNATURES.iter().any(|sig| bytes.starts_with(sig))
+}
+
+/// The decoded response of a GetFeatureInfo request
 #[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
-pub struct GetFeatureInfo {}
+pub struct GetFeatureInfo {
+  /// The MIME type of the response body
+  pub format: String,
+  /// The response body, as returned by the server
+  pub raw: String,
+  /// The features found at the query point
+  pub features: Vec<Feature>,
+}
+
+impl GetFeatureInfo {
+  /// Decode a GetFeatureInfo response body of the given MIME type.
+  ///
+  /// Supported formats are `text/plain`, `text/html`, `application/json` and GML
+  /// (`application/vnd.ogc.gml`, `text/xml`).
+  pub fn decode(format: &str, raw: String) -> crate::Result<Self> {
+    let features = feature_info::decode(format, &raw)?;
+    Ok(GetFeatureInfo {
+      format: format.to_string(),
+      raw,
+      features,
+    })
+  }
+}
 
 /// General service metadata
 #[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
@@ -201,77 +470,551 @@ pub struct Service {
   pub name: String,
   #[serde(rename = "Title", default)]
   pub title: String,
+  #[serde(rename = "KeywordList", default)]
+  pub keyword_list: KeywordList,
+  /// The top-level web address of the service or its provider
+  #[serde(rename = "OnlineResource", default)]
+  pub online_resource: OnlineResource,
+  #[serde(rename = "ContactInformation", default)]
+  pub contact_information: Option<ContactInformation>,
+  /// The fees for using the service, `none` if there are none
+  #[serde(rename = "Fees", default)]
+  pub fees: Option<String>,
+  /// The constraints on using the service, e.g. its licence, `none` if there are none
+  #[serde(rename = "AccessConstraints", default)]
+  pub access_constraints: Option<String>,
+  /// The maximum number of layers in a GetMap request
+  #[serde(rename = "LayerLimit", default)]
+  pub layer_limit: Option<u32>,
   #[serde(rename = "MaxWidth", default)]
   pub max_width: Option<u32>,
   #[serde(rename = "MaxHeight", default)]
   pub max_height: Option<u32>,
 }
 
+/// How to contact the provider of the service
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct ContactInformation {
+  #[serde(rename = "ContactPersonPrimary", default)]
+  pub contact_person_primary: Option<ContactPersonPrimary>,
+  #[serde(rename = "ContactPosition", default)]
+  pub contact_position: Option<String>,
+  #[serde(rename = "ContactAddress", default)]
+  pub contact_address: Option<ContactAddress>,
+  #[serde(rename = "ContactVoiceTelephone", default)]
+  pub contact_voice_telephone: Option<String>,
+  #[serde(rename = "ContactFacsimileTelephone", default)]
+  pub contact_facsimile_telephone: Option<String>,
+  #[serde(rename = "ContactElectronicMailAddress", default)]
+  pub contact_electronic_mail_address: Option<String>,
+}
+
+/// The person to contact and their organization
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct ContactPersonPrimary {
+  #[serde(rename = "ContactPerson", default)]
+  pub contact_person: String,
+  #[serde(rename = "ContactOrganization", default)]
+  pub contact_organization: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct ContactAddress {
+  /// The kind of address, e.g. `postal`
+  #[serde(rename = "AddressType", default)]
+  pub address_type: String,
+  #[serde(rename = "Address", default)]
+  pub address: String,
+  #[serde(rename = "City", default)]
+  pub city: String,
+  #[serde(rename = "StateOrProvince", default)]
+  pub state_or_province: String,
+  #[serde(rename = "PostCode", default)]
+  pub post_code: String,
+  #[serde(rename = "Country", default)]
+  pub country: String,
+}
+
 /// The root element
 #[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
 pub struct Capability {
+  /// The operations supported by the service
+  #[serde(rename = "Request", default)]
+  pub request: Request,
+  /// The formats in which the service can report exceptions
+  #[serde(rename = "Exception", default)]
+  pub exception: Exception,
+  /// SLD extension: how clients may symbolize the layers
+  #[serde(rename = "UserDefinedSymbolization", default)]
+  pub user_defined_symbolization: Option<UserDefinedSymbolization>,
   #[serde(rename = "Layer", default)]
   pub layer: Option<Layer>,
 }
 
+/// The Exception element, listing the EXCEPTIONS values the service accepts
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Exception {
+  #[serde(rename = "Format", default)]
+  pub formats: Vec<String>,
+}
+
+/// The Styled Layer Descriptor features supported by the service
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct UserDefinedSymbolization {
+  /// Whether the SLD and SLD_BODY parameters are supported
+  #[serde(rename = "SupportSLD", default, deserialize_with = "xml_bool")]
+  pub support_sld: bool,
+  /// Whether styled layer descriptors may define layers of their own
+  #[serde(rename = "UserLayer", default, deserialize_with = "xml_bool")]
+  pub user_layer: bool,
+  /// Whether styled layer descriptors may define styles of their own
+  #[serde(rename = "UserStyle", default, deserialize_with = "xml_bool")]
+  pub user_style: bool,
+  /// Whether user layers may take their features from a remote WFS
+  #[serde(rename = "RemoteWFS", default, deserialize_with = "xml_bool")]
+  pub remote_wfs: bool,
+}
+
+/// The operations offered by the service
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Request {
+  #[serde(rename = "GetCapabilities", default)]
+  pub get_capabilities: Operation,
+  #[serde(rename = "GetMap", default)]
+  pub get_map: Operation,
+  #[serde(rename = "GetFeatureInfo", default)]
+  pub get_feature_info: Option<Operation>,
+  /// SLD extension: DescribeLayer
+  #[serde(rename = "DescribeLayer", default)]
+  pub describe_layer: Option<Operation>,
+  /// SLD extension: GetLegendGraphic
+  #[serde(rename = "GetLegendGraphic", default)]
+  pub get_legend_graphic: Option<Operation>,
+  /// SLD extension: GetStyles
+  #[serde(rename = "GetStyles", default)]
+  pub get_styles: Option<Operation>,
+}
+
+/// The metadata of a single operation: its output formats and where to send it
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Operation {
+  /// The supported output formats (MIME types)
+  #[serde(rename = "Format", default)]
+  pub formats: Vec<String>,
+  /// The distributed computing platforms the operation is available on
+  #[serde(rename = "DCPType", default)]
+  pub dcp_type: Vec<DcpType>,
+}
+
+impl Operation {
+  /// The URL prefix for invoking this operation using HTTP GET
+  pub fn get_url(&self) -> Option<&str> {
+    self
+      .dcp_type
+      .iter()
+      .find_map(|dcp| dcp.http.get.as_ref())
+      .map(|m| m.online_resource.href.as_str())
+  }
+
+  /// The URL for invoking this operation using HTTP POST
+  pub fn post_url(&self) -> Option<&str> {
+    self
+      .dcp_type
+      .iter()
+      .find_map(|dcp| dcp.http.post.as_ref())
+      .map(|m| m.online_resource.href.as_str())
+  }
+
+  /// Whether the given format is advertised for this operation
+  pub fn supports_format(&self, format: &str) -> bool {
+    self.formats.iter().any(|f| f == format)
+  }
+}
+
+/// The DCPType element; HTTP is the only platform defined by the specification
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct DcpType {
+  #[serde(rename = "HTTP", default)]
+  pub http: Http,
+}
+
+/// The HTTP request methods an operation is available on
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Http {
+  #[serde(rename = "Get", default)]
+  pub get: Option<HttpMethod>,
+  #[serde(rename = "Post", default)]
+  pub post: Option<HttpMethod>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct HttpMethod {
+  #[serde(rename = "OnlineResource", default)]
+  pub online_resource: OnlineResource,
+}
+
+/// A link, as per the `xlink:href` attribute
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct OnlineResource {
+  #[serde(default)]
+  pub href: String,
+}
+
 #[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
 pub struct Layer {
   #[serde(rename = "Abstract", default)]
   pub abstr: String,
-  /// The LatLonBoundingBox element
+  /// The LatLonBoundingBox element (1.1.x), see `geographic_bbox`
   #[serde(rename = "LatLonBoundingBox", default)]
   pub ll_bbox: Option<LatLonBoundingBox>,
+  /// The EX_GeographicBoundingBox element (1.3.0), see `geographic_bbox`
+  #[serde(rename = "EX_GeographicBoundingBox", default)]
+  pub ex_bbox: Option<ExGeographicBoundingBox>,
   #[serde(rename = "BoundingBox", default)]
   pub bbox: Vec<BoundingBox>,
   #[serde(rename = "Name", default)]
   pub name: String,
+  /// Whether GetFeatureInfo requests are supported for this Layer, `None` when the attribute
+  /// is absent; see `queryable()`. The attributes replace the values of the parent Layer.
+  #[serde(default, deserialize_with = "optional_xml_bool")]
+  pub queryable: Option<bool>,
+  /// Whether the map has no transparent areas, e.g. a raster coverage
+  #[serde(default, deserialize_with = "optional_xml_bool")]
+  pub opaque: Option<bool>,
+  /// How many times this Layer has been cascaded from another WMS, 0 if it has not
+  #[serde(default)]
+  pub cascaded: Option<u32>,
+  /// Whether the map can only be requested for the full bounding box
+  #[serde(rename = "noSubsets", default, deserialize_with = "optional_xml_bool")]
+  pub no_subsets: Option<bool>,
+  /// The only width the map can be requested with, 0 if any width is allowed
+  #[serde(rename = "fixedWidth", default)]
+  pub fixed_width: Option<u32>,
+  /// The only height the map can be requested with, 0 if any height is allowed
+  #[serde(rename = "fixedHeight", default)]
+  pub fixed_height: Option<u32>,
+  /// The smallest scale denominator the Layer is rendered at (1.3.0)
+  #[serde(rename = "MinScaleDenominator", default)]
+  pub min_scale_denominator: Option<f64>,
+  /// The scale denominator from which on the Layer is no longer rendered (1.3.0)
+  #[serde(rename = "MaxScaleDenominator", default)]
+  pub max_scale_denominator: Option<f64>,
+  /// The scale range of the Layer (1.1.x), see `scale_denominators`
+  #[serde(rename = "ScaleHint", default)]
+  pub scale_hint: Option<ScaleHint>,
   #[serde(rename = "CRS", default)]
   crs: HashSet<String>,
   #[serde(rename = "SRS", default)]
   srs: HashSet<String>, // 1.1.0 compat
+  #[serde(rename = "Dimension", default)]
+  dimension: Vec<Dimension>,
+  #[serde(rename = "Extent", default)]
+  extent: Vec<Extent>, // 1.1.0 compat
 
   #[serde(rename = "KeywordList", default)]
-  pub keyword_list: KeywordList, 
+  pub keyword_list: KeywordList,
+  /// The source of the data, to credit on maps
+  #[serde(rename = "Attribution", default)]
+  pub attribution: Option<Attribution>,
+  /// The authorities defining the meaning of the `identifiers`
+  #[serde(rename = "AuthorityURL", default)]
+  pub authority_url: Vec<AuthorityUrl>,
+  #[serde(rename = "Identifier", default)]
+  pub identifiers: Vec<Identifier>,
+  /// Detailed metadata about the data, e.g. an ISO 19115 record
+  #[serde(rename = "MetadataURL", default)]
+  pub metadata_url: Vec<MetadataUrl>,
+  /// The underlying data, e.g. for download
+  #[serde(rename = "DataURL", default)]
+  pub data_url: Vec<ResourceUrl>,
+  /// A list of the features represented in the Layer
+  #[serde(rename = "FeatureListURL", default)]
+  pub feature_list_url: Vec<ResourceUrl>,
 
   #[serde(rename = "Title", default)]
   pub title: String,
+  /// The styles in which this Layer can be rendered
+  #[serde(rename = "Style", default)]
+  pub styles: Vec<Style>,
   #[serde(rename = "Layer", default)]
   pub layers: Vec<Layer>,
 }
 
 impl Layer {
-  /// The combined CRS values for this Layer
+  /// Whether GetFeatureInfo requests are supported, false unless declared
+  pub fn queryable(&self) -> bool {
+    self.queryable.unwrap_or(false)
+  }
+
+  /// Whether the map has no transparent areas, false unless declared
+  pub fn opaque(&self) -> bool {
+    self.opaque.unwrap_or(false)
+  }
+
+  /// How many times this Layer has been cascaded, 0 unless declared
+  pub fn cascaded(&self) -> u32 {
+    self.cascaded.unwrap_or(0)
+  }
+
+  /// Whether the map can only be requested for the full bounding box, false unless declared
+  pub fn no_subsets(&self) -> bool {
+    self.no_subsets.unwrap_or(false)
+  }
+
+  /// The only width the map can be requested with, 0 if any width is allowed
+  pub fn fixed_width(&self) -> u32 {
+    self.fixed_width.unwrap_or(0)
+  }
+
+  /// The only height the map can be requested with, 0 if any height is allowed
+  pub fn fixed_height(&self) -> u32 {
+    self.fixed_height.unwrap_or(0)
+  }
+
+  /// The combined CRS values for this Layer, excluding the inherited ones unless resolved
   pub fn crs(&self) -> HashSet<String> {
     let mut combined_crs = HashSet::new();
     combined_crs.extend(self.crs.clone());
     combined_crs.extend(self.srs.clone());
     combined_crs
   }
+
+  /// This Layer and all its descendants, depth first with parents before their children
+  pub fn iter(&self) -> LayerIter<'_> {
+    LayerIter {
+      queue: vec![self].into(),
+      breadth_first: false,
+    }
+  }
+
+  /// This Layer and all its descendants, level by level
+  pub fn iter_breadth_first(&self) -> LayerIter<'_> {
+    LayerIter {
+      queue: vec![self].into(),
+      breadth_first: true,
+    }
+  }
+
+  /// The first Layer of this tree with the given name
+  pub fn find(&self, name: &str) -> Option<&Layer> {
+    self.iter().find(|l| l.name == name)
+  }
+
+  /// Whether the KeywordList of this Layer has the given keyword, ignoring case
+  pub fn has_keyword(&self, keyword: &str) -> bool {
+    self
+      .keyword_list
+      .keyword
+      .iter()
+      .any(|k| k.trim().eq_ignore_ascii_case(keyword))
+  }
+
+  /// Whether this Layer is available in the given CRS, ignoring case
+  pub fn supports_crs(&self, crs: &str) -> bool {
+    self
+      .crs
+      .iter()
+      .chain(self.srs.iter())
+      .any(|c| c.eq_ignore_ascii_case(crs))
+  }
+
+  /// The geographic extent of this Layer whatever the version of the document, from the
+  /// EX_GeographicBoundingBox (1.3.0) or LatLonBoundingBox (1.1.x) element
+  pub fn geographic_bbox(&self) -> Option<LatLonBoundingBox> {
+    self
+      .ex_bbox
+      .as_ref()
+      .map(LatLonBoundingBox::from)
+      .or_else(|| self.ll_bbox.clone())
+  }
+
+  /// Whether the geographic bounding box of this Layer intersects the given one
+  pub fn intersects(&self, bbox: &LatLonBoundingBox) -> bool {
+    self.geographic_bbox().map_or(false, |b| {
+      b.minx <= bbox.maxx && bbox.minx <= b.maxx && b.miny <= bbox.maxy && bbox.miny <= b.maxy
+    })
+  }
+
+  /// The style of this Layer with the given name
+  pub fn style(&self, name: &str) -> Option<&Style> {
+    self.styles.iter().find(|s| s.name == name)
+  }
+
+  /// The sample dimensions declared by this Layer, combined with their 1.1.x `Extent`
+  pub fn dimensions(&self) -> Vec<Dimension> {
+    self
+      .dimension
+      .iter()
+      .map(|d| match self.extent.iter().find(|e| e.name == d.name) {
+        Some(extent) => d.clone().with_extent(extent),
+        None => d.clone(),
+      })
+      .collect()
+  }
+
+  /// A copy of this Layer tree where every Layer carries the properties it inherits from its
+  /// ancestors, as per section 7.2.4.8 of the WMS 1.3.0 specification
+  pub fn resolved(&self) -> Layer {
+    self.resolved_with(None)
+  }
+
+  fn resolved_with(&self, parent: Option<&Layer>) -> Layer {
+    let mut layer = self.clone();
+    if let Some(parent) = parent {
+      layer.inherit(parent);
+    }
+    let layers = self
+      .layers
+      .iter()
+      .map(|child| child.resolved_with(Some(&layer)))
+      .collect();
+    layer.layers = layers;
+    layer
+  }
+
+  /// Take the inheritable properties of the (already resolved) parent: CRSs and styles are
+  /// added to this Layer's own, the others are used where this Layer does not replace them.
+  /// Attributes are inherited when this Layer does not declare them, even as false or 0.
+  fn inherit(&mut self, parent: &Layer) {
+    self.queryable = self.queryable.or(parent.queryable);
+    self.opaque = self.opaque.or(parent.opaque);
+    self.no_subsets = self.no_subsets.or(parent.no_subsets);
+    self.cascaded = self.cascaded.or(parent.cascaded);
+    self.fixed_width = self.fixed_width.or(parent.fixed_width);
+    self.fixed_height = self.fixed_height.or(parent.fixed_height);
+    self.crs.extend(parent.crs.iter().cloned());
+    self.srs.extend(parent.srs.iter().cloned());
+    inherit_by(&mut self.styles, &parent.styles, |s| s.name.clone());
+    if self.attribution.is_none() {
+      self.attribution = parent.attribution.clone();
+    }
+    // The denominators are a range, which a Layer giving either of them replaces
+    if self.min_scale_denominator.is_none() && self.max_scale_denominator.is_none() {
+      self.min_scale_denominator = parent.min_scale_denominator;
+      self.max_scale_denominator = parent.max_scale_denominator;
+    }
+    if self.scale_hint.is_none() {
+      self.scale_hint = parent.scale_hint.clone();
+    }
+    inherit_by(&mut self.authority_url, &parent.authority_url, |a| {
+      a.name.clone()
+    });
+    if self.ll_bbox.is_none() && self.ex_bbox.is_none() {
+      self.ll_bbox = parent.ll_bbox.clone();
+      self.ex_bbox = parent.ex_bbox.clone();
+    }
+    inherit_by(&mut self.bbox, &parent.bbox, |b| b.srs.clone());
+    inherit_by(&mut self.dimension, &parent.dimension, |d| d.name.clone());
+    inherit_by(&mut self.extent, &parent.extent, |e| e.name.clone());
+  }
+}
+
+/// An iterator over a Layer tree, see `Layer::iter` and `Layer::iter_breadth_first`
+#[derive(Clone, Debug)]
+pub struct LayerIter<'a> {
+  queue: VecDeque<&'a Layer>,
+  breadth_first: bool,
+}
+
+impl<'a> Iterator for LayerIter<'a> {
+  type Item = &'a Layer;
+
+  fn next(&mut self) -> Option<&'a Layer> {
+    let layer = self.queue.pop_front()?;
+    if self.breadth_first {
+      self.queue.extend(layer.layers.iter());
+    } else {
+      for child in layer.layers.iter().rev() {
+        self.queue.push_front(child);
+      }
+    }
+    Some(layer)
+  }
+}
+
+/// Prepend the parent's items whose key none of the child's items have
+fn inherit_by<T, K, F>(own: &mut Vec<T>, parent: &[T], key: F)
+where
+  T: Clone,
+  K: PartialEq,
+  F: Fn(&T) -> K,
+{
+  let inherited: Vec<T> = parent
+    .iter()
+    .filter(|p| !own.iter().any(|o| key(o) == key(p)))
+    .cloned()
+    .collect();
+  own.splice(0..0, inherited);
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
 pub struct KeywordList {
   #[serde(rename = "Keyword", default)]
-  pub keyword: Vec<String>
+  pub keyword: Vec<String>,
 }
 
+/// The geographic extent in decimal degrees, longitude first (1.1.x `LatLonBoundingBox`)
 #[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
 pub struct LatLonBoundingBox {
-  pub minx: f32,
-  pub miny: f32,
-  pub maxx: f32,
-  pub maxy: f32,
+  pub minx: f64,
+  pub miny: f64,
+  pub maxx: f64,
+  pub maxy: f64,
+}
+
+/// The geographic extent in decimal degrees (1.3.0 `EX_GeographicBoundingBox`)
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct ExGeographicBoundingBox {
+  #[serde(rename = "westBoundLongitude")]
+  pub west_bound_longitude: f64,
+  #[serde(rename = "eastBoundLongitude")]
+  pub east_bound_longitude: f64,
+  #[serde(rename = "southBoundLatitude")]
+  pub south_bound_latitude: f64,
+  #[serde(rename = "northBoundLatitude")]
+  pub north_bound_latitude: f64,
+}
+
+impl From<&ExGeographicBoundingBox> for LatLonBoundingBox {
+  fn from(b: &ExGeographicBoundingBox) -> Self {
+    LatLonBoundingBox {
+      minx: b.west_bound_longitude,
+      miny: b.south_bound_latitude,
+      maxx: b.east_bound_longitude,
+      maxy: b.north_bound_latitude,
+    }
+  }
+}
+
+impl From<&LatLonBoundingBox> for ExGeographicBoundingBox {
+  fn from(b: &LatLonBoundingBox) -> Self {
+    ExGeographicBoundingBox {
+      west_bound_longitude: b.minx,
+      east_bound_longitude: b.maxx,
+      south_bound_latitude: b.miny,
+      north_bound_latitude: b.maxy,
+    }
+  }
 }
 
+/// An extent in a given CRS. Capabilities documents give it in the axis order of the CRS for
+/// 1.3.0, i.e. latitude first for EPSG:4326, and in x/y order for 1.1.x.
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct BoundingBox {
-  pub minx: f32,
-  pub miny: f32,
-  pub maxx: f32,
-  pub maxy: f32,
+  pub minx: f64,
+  pub miny: f64,
+  pub maxx: f64,
+  pub maxy: f64,
 
-  #[serde(rename = "SRS", default)]
+  /// The CRS (1.3.0) or SRS (1.1.x) of the coordinates
+  #[serde(rename = "SRS", alias = "CRS", default)]
   pub srs: String,
+  /// The spatial resolution of the data along the x axis, in CRS units
+  #[serde(default)]
+  pub resx: Option<f64>,
+  /// The spatial resolution of the data along the y axis, in CRS units
+  #[serde(default)]
+  pub resy: Option<f64>,
 }
 
 impl Default for BoundingBox {
@@ -282,6 +1025,8 @@ impl Default for BoundingBox {
       miny: -90.0,
       maxx: 180.0,
       maxy: 90.0,
+      resx: None,
+      resy: None,
     }
   }
 }
@@ -293,38 +1038,175 @@ impl BoundingBox {
   }
 }
 
+/// The 1.1.x range of scales a Layer is rendered at, given as the ground distances covered
+/// by the diagonal of a pixel, in the units of the CRS; see `ScaleHint::scale_denominators`
 #[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
 pub struct ScaleHint {
-  pub min: f32,
-  pub max: f32,
+  #[serde(default)]
+  pub min: f64,
+  #[serde(default)]
+  pub max: f64,
 }
 
+/// A rendering style offered for a layer; its name is a valid STYLES value for the layer
 #[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
 pub struct Style {
   #[serde(rename = "Name", default)]
-  name: String,
+  pub name: String,
   #[serde(rename = "Title", default)]
-  title: String,
+  pub title: String,
   #[serde(rename = "Abstract", default)]
-  abstr: Option<String>,
+  pub abstr: Option<String>,
+  /// Pictures of the legend, e.g. in several sizes or formats
   #[serde(rename = "LegendURL", default)]
-  legend_url: Vec<String>,
+  pub legend_url: Vec<LegendUrl>,
+  /// The stylesheet describing how the layer's data is rendered, e.g. an XSL file
   #[serde(rename = "StyleSheetURL", default)]
-  style_sheet_url: Option<String>,
+  pub style_sheet_url: Option<ResourceUrl>,
+  /// The style's definition (1.1.x only)
   #[serde(rename = "StyleURL", default)]
-  style_url: Option<String>,
+  pub style_url: Option<ResourceUrl>,
+}
+
+/// The location of a picture of a style's legend
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct LegendUrl {
+  /// The width of the picture in pixels
+  #[serde(default)]
+  pub width: u32,
+  /// The height of the picture in pixels
+  #[serde(default)]
+  pub height: u32,
+  /// The MIME type of the picture
+  #[serde(rename = "Format", default)]
+  pub format: String,
+  #[serde(rename = "OnlineResource", default)]
+  pub online_resource: OnlineResource,
+}
+
+/// The location of a logo picture, laid out like a LegendURL
+pub type LogoUrl = LegendUrl;
+
+/// The provider of a Layer's data
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Attribution {
+  /// The name of the provider, e.g. for a credit line
+  #[serde(rename = "Title", default)]
+  pub title: Option<String>,
+  /// The web site of the provider
+  #[serde(rename = "OnlineResource", default)]
+  pub online_resource: Option<OnlineResource>,
+  #[serde(rename = "LogoURL", default)]
+  pub logo_url: Option<LogoUrl>,
+}
+
+/// An authority whose identifiers are given in the `Identifier` elements of a Layer
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct AuthorityUrl {
+  /// The name the identifiers refer to the authority by
+  #[serde(default)]
+  pub name: String,
+  #[serde(rename = "OnlineResource", default)]
+  pub online_resource: OnlineResource,
+}
+
+/// The identifier of a Layer's data within the namespace of an authority
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Identifier {
+  /// The `name` of the `AuthorityUrl`
+  #[serde(default)]
+  pub authority: String,
+  #[serde(rename = "$value", default)]
+  pub value: String,
+}
+
+/// The location of a metadata record and the standard it conforms to
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct MetadataUrl {
+  /// The metadata standard, e.g. `ISO19115:2003` or `FGDC:1998`
+  #[serde(rename = "type", default)]
+  pub metadata_type: String,
+  #[serde(rename = "Format", default)]
+  pub format: String,
+  #[serde(rename = "OnlineResource", default)]
+  pub online_resource: OnlineResource,
+}
+
+/// The location of a document and its MIME type
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct ResourceUrl {
+  #[serde(rename = "Format", default)]
+  pub format: String,
+  #[serde(rename = "OnlineResource", default)]
+  pub online_resource: OnlineResource,
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
 pub struct GetCapabilities {
+  /// The version of the document, as negotiated with the server
+  #[serde(default)]
+  pub version: String,
+  /// Increased by the server whenever the capabilities change
+  #[serde(rename = "updateSequence", default)]
+  pub update_sequence: Option<String>,
   #[serde(rename = "Service", default)]
   pub service: Service,
   #[serde(rename = "Capability", default)]
   pub capability: Capability,
 }
 
+/// Queries over the layer tree. Inheritable properties such as the CRSs are those declared
+/// by each layer itself; query `resolved_layers` to take inheritance into account, e.g.
+/// `capa.resolved_layers().into_iter().filter(|l| l.supports_crs("EPSG:4326"))`.
+impl GetCapabilities {
+  /// The named (i.e. requestable) layers, depth first with parents before their children
+  pub fn layers(&self) -> impl Iterator<Item = &Layer> {
+    self
+      .capability
+      .layer
+      .iter()
+      .flat_map(Layer::iter)
+      .filter(|l| !l.name.is_empty())
+  }
+
+  /// The named layers, level by level
+  pub fn layers_breadth_first(&self) -> impl Iterator<Item = &Layer> {
+    self
+      .capability
+      .layer
+      .iter()
+      .flat_map(Layer::iter_breadth_first)
+      .filter(|l| !l.name.is_empty())
+  }
+
+  /// The layer with the given name
+  pub fn layer(&self, name: &str) -> Option<&Layer> {
+    self.capability.layer.as_ref()?.find(name)
+  }
+
+  /// The named (i.e. requestable) layers in document order, each carrying the properties it
+  /// inherits from its ancestors; see `Layer::resolved`
+  pub fn resolved_layers(&self) -> Vec<Layer> {
+    fn named(layer: Layer, into: &mut Vec<Layer>) {
+      let children = layer.layers.clone();
+      if !layer.name.is_empty() {
+        into.push(layer);
+      }
+      for child in children {
+        named(child, into);
+      }
+    }
+    let mut layers = Vec::new();
+    if let Some(root) = &self.capability.layer {
+      named(root.resolved(), &mut layers);
+    }
+    layers
+  }
+}
+
 /// The parameters for a GetMap service request, as per [the WMS test data spec](http://cite.opengeospatial.org/OGCTestData/wms/1.1.1/spec/wms1.1.1.html#wmsops.getmap).
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
 pub struct GetMapParameters {
   /// Request version.  
   pub version: String,
@@ -332,9 +1214,11 @@ pub struct GetMapParameters {
   pub layers: Vec<String>,
   /// Comma-separated list of one rendering style per requested layer. Optional if SLD parameter is present.
   pub styles: Vec<String>,
-  /// namespace:identifier - Spatial Reference System.
+  /// namespace:identifier - Spatial Reference System. Sent as CRS for 1.3.0 and SRS otherwise.
   pub srs: String,
   /// minx,miny,maxx,maxy R Bounding box corners (lower left, upper right) in SRS units.
+  /// Always given with x as easting/longitude; the axes are swapped when the request
+  /// version and CRS call for latitude/longitude order.
   pub bbox: BoundingBox,
   /// Width in pixels of map picture.  
   pub width: u16,
@@ -352,22 +1236,155 @@ pub struct GetMapParameters {
   pub time: Option<String>,
   /// Elevation of layer desired.
   pub elevation: Option<String>,
+  /// URL of a Styled Layer Descriptor to use instead of, or to style, LAYERS.
+  pub sld: Option<String>,
+  /// A Styled Layer Descriptor document given inline; takes precedence over SLD.
+  pub sld_body: Option<String>,
+  /// Version of the Styled Layer Descriptor specification used by SLD or SLD_BODY.
+  pub sld_version: Option<String>,
+  /// Additional vendor specific parameters, e.g. GeoServer's `CQL_FILTER`, sent as given.
+  pub vendor: Vec<(String, String)>,
 }
 
+/// The parameter names of GetMap and GetFeatureInfo, which vendor parameters may not reuse
+const STANDARD_PARAMETERS: [&str; 28] = [
+  "SERVICE",
+  "REQUEST",
+  "VERSION",
+  "LAYERS",
+  "STYLES",
+  "SRS",
+  "CRS",
+  "BBOX",
+  "WIDTH",
+  "HEIGHT",
+  "FORMAT",
+  "TRANSPARENT",
+  "BGCOLOR",
+  "EXCEPTIONS",
+  "TIME",
+  "ELEVATION",
+  "SLD",
+  "SLD_BODY",
+  "SLD_VERSION",
+  "QUERY_LAYERS",
+  "INFO_FORMAT",
+  "FEATURE_COUNT",
+  "I",
+  "J",
+  "X",
+  "Y",
+  "WMTVER",
+  "UPDATESEQUENCE",
+];
+
 impl GetMapParameters {
-  fn layers_to_csv(&self) -> String {
-    if self.layers.len() > 1 {
-      self.layers.join(",")
+  /// Start building parameters that keep one style per layer, e.g.
+  /// ```
+  /// use ogc::wms::GetMapParameters;
+  /// let params = GetMapParameters::builder()
+  ///   .layer("roads")
+  ///   .styled_layer("rivers", "blue")
+  ///   .vendor("CQL_FILTER", "width > 2;INCLUDE")
+  ///   .build()
+  ///   .unwrap();
+  /// assert_eq!(params.styles, vec!["", "blue"]);
+  /// ```
+  pub fn builder() -> GetMapBuilder {
+    GetMapBuilder::default()
+  }
+
+  /// Check that the parameters can be encoded as a request: LAYERS may only be omitted when
+  /// a Styled Layer Descriptor is given, STYLES must be empty or give one style per layer
+  /// and vendor parameters must not clash with the standard ones.
+  pub fn check(&self) -> crate::Result<()> {
+    if self.layers.is_empty() && self.sld.is_none() && self.sld_body.is_none() {
+      return Err(Error::InvalidParameter(
+        "LAYERS is required unless SLD or SLD_BODY is given".to_string(),
+      ));
+    }
+    if !self.styles.is_empty() && self.styles.len() != self.layers.len() {
+      return Err(Error::InvalidParameter(format!(
+        "STYLES gives {} styles for {} layers",
+        self.styles.len(),
+        self.layers.len()
+      )));
+    }
+    for (name, _) in self.vendor.iter() {
+      if STANDARD_PARAMETERS
+        .iter()
+        .any(|p| p.eq_ignore_ascii_case(name))
+      {
+        return Err(Error::InvalidParameter(format!(
+          "Vendor parameter {} clashes with a standard parameter",
+          name
+        )));
+      }
+    }
+    Ok(())
+  }
+
+  /// Append the GetMap request parameters (excluding REQUEST) to the given URL
+  fn append_query_pairs(&self, url: &mut Url) {
+    let mut pairs = url.query_pairs_mut();
+    pairs
+      .append_pair("SERVICE", "WMS")
+      .append_pair("VERSION", &self.version);
+    // A Styled Layer Descriptor may name the layers and styles itself
+    if !self.layers.is_empty() || (self.sld.is_none() && self.sld_body.is_none()) {
+      pairs
+        .append_pair("LAYERS", &self.layers.join(","))
+        .append_pair("STYLES", &self.styles_to_csv());
+    }
+    pairs
+      .append_pair(
+        if is_v1_3(&self.version) { "CRS" } else { "SRS" },
+        &self.srs,
+      )
+      .append_pair("BBOX", &self.bbox_to_str())
+      .append_pair("WIDTH", &self.width.to_string())
+      .append_pair("HEIGHT", &self.height.to_string())
+      .append_pair("FORMAT", &self.format)
+      .append_pair(
+        "TRANSPARENT",
+        &self.transparent.unwrap_or(true).to_string().to_uppercase(),
+      );
+    let optional = [
+      ("BGCOLOR", &self.bg_color),
+      ("EXCEPTIONS", &self.exceptions),
+      ("TIME", &self.time),
+      ("ELEVATION", &self.elevation),
+      ("SLD", &self.sld),
+      ("SLD_BODY", &self.sld_body),
+      ("SLD_VERSION", &self.sld_version),
+    ];
+    for (name, value) in optional.iter() {
+      if let Some(value) = value {
+        pairs.append_pair(name, value);
+      }
+    }
+    for (name, value) in self.vendor.iter() {
+      pairs.append_pair(name, value);
+    }
+  }
+
+  /// The BBOX value in the axis order of the request's CRS: WMS 1.3.0 honours the CRS
+  /// definition, so geographic CRSs such as EPSG:4326 are latitude first.
+  fn bbox_to_str(&self) -> String {
+    if is_v1_3(&self.version) && is_lat_lon_crs(&self.srs) {
+      let b = &self.bbox;
+      format!("{},{},{},{}", b.miny, b.minx, b.maxy, b.maxx)
     } else {
-      self.layers[0].clone()
+      self.bbox.to_str()
     }
   }
 
+  /// The STYLES value; an empty value requests the default style of every layer
   fn styles_to_csv(&self) -> String {
-    match self.styles.len() {
-      0 => "".to_string(),
-      1 => self.styles.join(","),
-      _ => self.styles[0].clone(),
+    if self.styles.iter().all(String::is_empty) {
+      String::new()
+    } else {
+      self.styles.join(",")
     }
   }
 }
@@ -388,19 +1405,299 @@ impl Default for GetMapParameters {
       exceptions: None,
       time: None,
       elevation: None,
+      sld: None,
+      sld_body: None,
+      sld_version: None,
+      vendor: Vec::new(),
     }
   }
 }
 
-#[cfg(test)]
-mod tests {
-  use crate::wms::{BoundingBox, GetCapabilities, GetMapParameters, WebMappingService, Wms};
-  use std::fs::read_to_string;
-  use std::fs::File;
-  use std::io::Write;
+/// Builds `GetMapParameters`, adding a style (possibly the default one) with every layer
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GetMapBuilder {
+  params: GetMapParameters,
+}
 
-  struct ParseExpectation {
-    service_name: String,
+impl GetMapBuilder {
+  pub fn version(mut self, version: &str) -> Self {
+    self.params.version = version.to_string();
+    self
+  }
+
+  /// Add a layer in its default style
+  pub fn layer(self, name: &str) -> Self {
+    self.styled_layer(name, "")
+  }
+
+  /// Add a layer in the given style
+  pub fn styled_layer(mut self, name: &str, style: &str) -> Self {
+    self.params.layers.push(name.to_string());
+    self.params.styles.push(style.to_string());
+    self
+  }
+
+  /// The CRS, along with the bounding box in that CRS
+  pub fn bbox(mut self, bbox: BoundingBox) -> Self {
+    self.params.srs = bbox.srs.clone();
+    self.params.bbox = bbox;
+    self
+  }
+
+  pub fn size(mut self, width: u16, height: u16) -> Self {
+    self.params.width = width;
+    self.params.height = height;
+    self
+  }
+
+  pub fn format(mut self, format: &str) -> Self {
+    self.params.format = format.to_string();
+    self
+  }
+
+  pub fn transparent(mut self, transparent: bool) -> Self {
+    self.params.transparent = Some(transparent);
+    self
+  }
+
+  pub fn bg_color(mut self, bg_color: &str) -> Self {
+    self.params.bg_color = Some(bg_color.to_string());
+    self
+  }
+
+  pub fn exceptions(mut self, exceptions: &str) -> Self {
+    self.params.exceptions = Some(exceptions.to_string());
+    self
+  }
+
+  pub fn time(mut self, time: &str) -> Self {
+    self.params.time = Some(time.to_string());
+    self
+  }
+
+  pub fn elevation(mut self, elevation: &str) -> Self {
+    self.params.elevation = Some(elevation.to_string());
+    self
+  }
+
+  /// Style the map with the Styled Layer Descriptor at this URL
+  pub fn sld(mut self, url: &str) -> Self {
+    self.params.sld = Some(url.to_string());
+    self
+  }
+
+  /// Style the map with this Styled Layer Descriptor document
+  pub fn sld_body(mut self, sld: &str) -> Self {
+    self.params.sld_body = Some(sld.to_string());
+    self
+  }
+
+  pub fn sld_version(mut self, version: &str) -> Self {
+    self.params.sld_version = Some(version.to_string());
+    self
+  }
+
+  /// Add a vendor specific parameter, e.g. `CQL_FILTER` or `viewparams`
+  pub fn vendor(mut self, name: &str, value: &str) -> Self {
+    self
+      .params
+      .vendor
+      .push((name.to_string(), value.to_string()));
+    self
+  }
+
+  /// The parameters, if they pass `GetMapParameters::check`
+  pub fn build(self) -> crate::Result<GetMapParameters> {
+    self.params.check()?;
+    Ok(self.params)
+  }
+}
+
+/// Whether the version is WMS 1.3.x, which renamed SRS to CRS and I/J to X/Y
+fn is_v1_3(version: &str) -> bool {
+  version.starts_with("1.3")
+}
+
+/// Deserialize an XML boolean attribute, which may be `0`/`1` as well as `false`/`true`
+fn xml_bool<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+  D: serde::Deserializer<'de>,
+{
+  let value: String = serde::Deserialize::deserialize(deserializer)?;
+  Ok(matches!(value.trim(), "1" | "true"))
+}
+
+/// Deserialize an optional XML boolean attribute, see `xml_bool`
+fn optional_xml_bool<'de, D>(deserializer: D) -> Result<Option<bool>, D::Error>
+where
+  D: serde::Deserializer<'de>,
+{
+  xml_bool(deserializer).map(Some)
+}
+
+/// The codes of the EPSG geographic range (4000-4999) that are not taken by a geocentric or
+/// projected CRS, besides those in the runs of `is_geographic_3d_run`. Codes the EPSG
+/// dataset leaves unassigned are included, as a new CRS in the range is most likely
+/// geographic. The list is the complement of the geocentric and projected codes, which can
+/// be listed from the EPSG tables of PROJ's `proj.db` to check or update it:
+/// ```sql
+/// SELECT code FROM geodetic_crs WHERE auth_name = 'EPSG' AND type = 'geocentric'
+/// UNION SELECT code FROM projected_crs WHERE auth_name = 'EPSG';
+/// ```
+const GEOGRAPHIC_EPSG: [RangeInclusive<u32>; 43] = [
+  4001..=4025,
+  4027..=4036,
+  4040..=4047,
+  4052..=4055,
+  4064..=4070,
+  4072..=4072,
+  4074..=4078,
+  4080..=4081,
+  4084..=4086,
+  4089..=4092,
+  4097..=4216,
+  4218..=4327,
+  4329..=4329,
+  4339..=4339,
+  4389..=4389,
+  4416..=4416,
+  4435..=4436,
+  4440..=4454,
+  4458..=4461,
+  4463..=4464,
+  4466..=4466,
+  4469..=4470,
+  4472..=4472,
+  4475..=4478,
+  4480..=4480,
+  4482..=4483,
+  4490..=4490,
+  4555..=4555,
+  4557..=4558,
+  4560..=4567,
+  4590..=4881,
+  4883..=4883,
+  4885..=4885,
+  4887..=4887,
+  4889..=4889,
+  4891..=4891,
+  4893..=4893,
+  4895..=4895,
+  4898..=4898,
+  4900..=4905,
+  4907..=4909,
+  4921..=4921,
+  4999..=4999,
+];
+
+/// The runs of alternating geocentric (even) and geographic 3D (odd) CRSs
+fn is_geographic_3d_run(code: u32) -> bool {
+  code % 2 == 1 && ((4340..=4388).contains(&code) || (4922..=4998).contains(&code))
+}
+
+/// Whether the CRS is geographic with latitude/longitude axis order.
+///
+/// `CRS:84` and friends are longitude first by definition. Without an EPSG database the
+/// EPSG geographic range (4000-4999) is used, excluding the geocentric and projected CRSs
+/// it also holds, e.g. the geocentric WGS 84 (4978).
+pub fn is_lat_lon_crs(crs: &str) -> bool {
+  let upper = crs.to_uppercase();
+  if !upper.contains("EPSG") {
+    return false;
+  }
+  match upper.rsplit(':').next().map(str::parse::<u32>) {
+    Some(Ok(code)) => {
+      is_geographic_3d_run(code) || GEOGRAPHIC_EPSG.iter().any(|r| r.contains(&code))
+    }
+    _ => false,
+  }
+}
+
+/// The parameters for a GetFeatureInfo service request. The query point is given in pixels
+/// relative to the map described by the embedded GetMap parameters.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct GetFeatureInfoParameters {
+  /// The GetMap request the query point refers to.
+  pub map: GetMapParameters,
+  /// Comma-separated list of one or more layers to be queried.
+  pub query_layers: Vec<String>,
+  /// Return format of feature information (MIME type).
+  pub info_format: String,
+  /// Number of features about which to return information (default=1).
+  pub feature_count: Option<u32>,
+  /// Column of the query point in pixels, sent as I (1.3.0) or X (1.1.x).
+  pub i: u32,
+  /// Row of the query point in pixels, sent as J (1.3.0) or Y (1.1.x).
+  pub j: u32,
+}
+
+impl GetFeatureInfoParameters {
+  /// Append the GetFeatureInfo specific parameters to the given URL
+  fn append_query_pairs(&self, url: &mut Url) {
+    let (i, j) = if is_v1_3(&self.map.version) {
+      ("I", "J")
+    } else {
+      ("X", "Y")
+    };
+    let mut pairs = url.query_pairs_mut();
+    pairs
+      .append_pair("QUERY_LAYERS", &self.query_layers.join(","))
+      .append_pair("INFO_FORMAT", &self.info_format)
+      .append_pair(i, &self.i.to_string())
+      .append_pair(j, &self.j.to_string());
+    if let Some(count) = self.feature_count {
+      pairs.append_pair("FEATURE_COUNT", &count.to_string());
+    }
+  }
+}
+
+impl Default for GetFeatureInfoParameters {
+  fn default() -> Self {
+    GetFeatureInfoParameters {
+      map: GetMapParameters::default(),
+      query_layers: Vec::new(),
+      info_format: "text/plain".to_string(),
+      feature_count: None,
+      i: 0,
+      j: 0,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::http::{HttpResponse, MockClient};
+  use crate::wms::{
+    is_lat_lon_crs, BoundingBox, ExceptionCode, GetCapabilities, GetFeatureInfoParameters,
+    GetMapParameters, LatLonBoundingBox, Layer, LegendUrl, OnlineResource, WebMappingService, Wms,
+  };
+  use crate::Error;
+  use std::fs::{read, read_to_string};
+
+  /// Parse a capabilities document, e.g. one written out by a test
+  pub(crate) async fn parse_capabilities(xml: &str) -> GetCapabilities {
+    WebMappingService::from_string(xml.to_string())
+      .get_capabilities()
+      .await
+      .unwrap()
+  }
+
+  /// Parse the example capabilities of the version, with each snippet replaced once to add
+  /// what a test needs to the document
+  pub(crate) async fn example_capabilities(
+    version: &str,
+    replacements: &[(&str, &str)],
+  ) -> GetCapabilities {
+    let mut xml = read_to_string(format!("./examples/WMS-{}.xml", version)).unwrap();
+    for (snippet, replacement) in replacements {
+      assert!(xml.contains(snippet), "{:?} is not in the example", snippet);
+      xml = xml.replacen(snippet, replacement, 1);
+    }
+    parse_capabilities(&xml).await
+  }
+
+  struct ParseExpectation {
+    service_name: String,
     service_title: String,
     service_abstr: String,
     inner_layers_len: usize,
@@ -422,7 +1719,7 @@ mod tests {
     assert_eq!(layer_list.layers.len(), exp.inner_layers_len);
     for layer in layer_list.layers.iter() {
       if !exp.skip_llbbox {
-        assert!(layer.ll_bbox.is_some());
+        assert!(layer.geographic_bbox().is_some());
       }
       if !exp.skip_layer_bbox {
         assert!(!layer.bbox.is_empty());
@@ -439,41 +1736,446 @@ mod tests {
 
   #[tokio::test]
   async fn test_get_map() {
-    //<Name>ROADS_RIVERS</Name>
-    //<CRS>EPSG:26986</CRS> <!-- An additional CRS for this layer -->
-    //<BoundingBox CRS="CRS:84"
-    //minx="-71.63" miny="41.75" maxx="-70.78" maxy="42.90" resx="0.01" resy="0.01"/>
+    let png = b"\x89PNG\r\n\x1a\nrest of the image".to_vec();
+    let client = MockClient::default()
+      .with_response(
+        "GetCapabilities",
+        HttpResponse::ok("text/xml", read("./examples/WMS-1.3.0.xml").unwrap()),
+      )
+      .with_response("GetMap", HttpResponse::ok("image/png", png.clone()));
+    let mut wms =
+      WebMappingService::from_url_with_client("http://example.com/wms".to_string(), client.clone())
+        .unwrap();
+    wms.get_capabilities().await.unwrap();
     let params = GetMapParameters {
-      layers: vec!["OSM-WMS".to_string()],
-      srs: "EPSG:4326".to_string(),
-      bbox: BoundingBox {
-        srs: "EPSG:4326".to_string(),
-        minx: -180.0,
-        miny: -90.0,
-        maxx: 180.0,
-        maxy: 90.0,
-      },
+      layers: vec!["ROADS_RIVERS".to_string()],
+      srs: "EPSG:26986".to_string(),
       ..GetMapParameters::default()
     };
-    let url =
-      "http://ows.mundialis.de/services/service?SERVICE=WMS&REQUEST=GetCapabilities&VERSION=1.3.0"
-        .to_string();
-    let get_map_res = WebMappingService::from_url(url)
-      .unwrap()
-      .get_map(params)
-      .await;
-    match get_map_res {
-      Ok(bytes) => {
-        assert_ne!(bytes.len(), 0);
-        let mut file = File::create("/tmp/test-get-map.png").unwrap();
-        assert!(file.write_all(&bytes).is_ok());
-      }
-      Err(e) => {
-        panic!(e);
+    assert_eq!(wms.get_map(params).await.unwrap(), png);
+
+    let requests = client.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].host_str(), Some("example.com"));
+    // GetMap goes to the OnlineResource advertised in the capabilities
+    assert_eq!(requests[1].host_str(), Some("hostname"));
+    assert_eq!(
+      query_value(&requests[1], "LAYERS"),
+      Some("ROADS_RIVERS".to_string())
+    );
+  }
+
+  #[tokio::test]
+  async fn test_get_map_service_exception() {
+    let report = r#"<ServiceExceptionReport version="1.3.0">
+        <ServiceException code="InvalidFormat">Unknown format</ServiceException>
+      </ServiceExceptionReport>"#;
+    let client = MockClient::default()
+      .with_response(
+        "GetMap",
+        HttpResponse::ok("application/vnd.ogc.se_xml", report.into()),
+      )
+      .with_response(
+        "GetFeatureInfo",
+        HttpResponse::ok("application/pdf", Vec::new()),
+      );
+    let mut wms =
+      WebMappingService::from_url_with_client("http://example.com/wms".to_string(), client)
+        .unwrap();
+    let params = GetMapParameters {
+      layers: vec!["ROADS_RIVERS".to_string()],
+      ..GetMapParameters::default()
+    };
+    let info = GetFeatureInfoParameters {
+      map: params.clone(),
+      ..GetFeatureInfoParameters::default()
+    };
+    match wms.get_map(params).await {
+      Err(Error::ServiceException(report)) => {
+        assert_eq!(report.code(), Some(&ExceptionCode::InvalidFormat))
       }
+      other => panic!("Unexpected response {:?}", other),
+    }
+    match wms.get_feature_info(info).await {
+      Err(Error::UnsupportedContentType(ct)) => assert_eq!(ct, "application/pdf"),
+      other => panic!("Unexpected response {:?}", other),
     }
   }
 
+  #[tokio::test]
+  async fn test_layer_styles_v1_3_0() {
+    let xml = read_to_string("./examples/WMS-1.3.0.xml").unwrap();
+    let capa = WebMappingService::from_string(xml)
+      .get_capabilities()
+      .await
+      .unwrap();
+    let roads_rivers = &capa.capability.layer.unwrap().layers[0];
+    let usgs = roads_rivers.style("USGS").unwrap();
+    assert_eq!(usgs.title, "USGS Topo Map Style");
+    assert!(usgs
+      .abstr
+      .as_ref()
+      .unwrap()
+      .starts_with("Features are shown"));
+    assert_eq!(
+      usgs.legend_url,
+      vec![LegendUrl {
+        width: 72,
+        height: 72,
+        format: "image/gif".to_string(),
+        online_resource: OnlineResource {
+          href: "http://www.university.edu/legends/usgs.gif".to_string(),
+        },
+      }]
+    );
+    let style_sheet = usgs.style_sheet_url.as_ref().unwrap();
+    assert_eq!(style_sheet.format, "text/xsl");
+    assert_eq!(
+      style_sheet.online_resource.href,
+      "http://www.university.edu/stylesheets/usgs.xsl"
+    );
+    assert_eq!(usgs.style_url, None);
+    assert!(roads_rivers.style("ATLAS").is_none());
+    assert_eq!(roads_rivers.layers[0].styles[0].name, "ATLAS");
+    assert!(roads_rivers.layers[1].styles.is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_layer_styles_v1_1_1() {
+    let xml = read_to_string("./examples/WMS-1.1.1.xml").unwrap();
+    let capa = WebMappingService::from_string(xml)
+      .get_capabilities()
+      .await
+      .unwrap();
+    let layers = capa.capability.layer.unwrap().layers;
+    assert_eq!(layers.iter().filter(|l| l.styles.is_empty()).count(), 4);
+    let style = layers.iter().find_map(|l| l.styles.first()).unwrap();
+    let legend = &style.legend_url[0];
+    assert_eq!((legend.width, legend.height), (20, 20));
+    assert_eq!(legend.format, "image/png");
+    assert!(legend
+      .online_resource
+      .href
+      .contains("request=GetLegendGraphic"));
+  }
+
+  #[tokio::test]
+  async fn test_resolved_layers_v1_3_0() {
+    let xml = read_to_string("./examples/WMS-1.3.0.xml").unwrap();
+    let capa = WebMappingService::from_string(xml)
+      .get_capabilities()
+      .await
+      .unwrap();
+    let layers = capa.resolved_layers();
+    let names: Vec<&str> = layers.iter().map(|l| l.name.as_str()).collect();
+    assert_eq!(
+      names,
+      vec![
+        "ROADS_RIVERS",
+        "ROADS_1M",
+        "RIVERS_1M",
+        "Clouds",
+        "Temperature",
+        "Pressure",
+        "ozone_image",
+        "population"
+      ]
+    );
+    // CRSs are added to the ancestors' ones
+    let roads = &layers[1];
+    assert_eq!(
+      roads.crs(),
+      ["CRS:84", "EPSG:26986"]
+        .iter()
+        .map(|c| c.to_string())
+        .collect()
+    );
+    assert!(layers.iter().all(|l| l.crs().contains("CRS:84")));
+    // Styles are added too
+    let styles: Vec<&str> = roads.styles.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(styles, vec!["USGS", "ATLAS"]);
+    assert_eq!(layers[2].styles.len(), 1);
+    // Dimensions are inherited unless replaced
+    let clouds = &layers[3];
+    assert_eq!(clouds.dimensions().len(), 1);
+    assert_eq!(clouds.dimensions()[0].name, "time");
+    let pressure = &layers[5];
+    assert_eq!(pressure.dimensions().len(), 3);
+    // The unresolved tree is left as it is
+    let root = capa.capability.layer.unwrap();
+    assert!(root.layers[0].layers[0].crs().is_empty());
+    assert_eq!(root.resolved().layers[0].layers[0].crs(), roads.crs());
+  }
+
+  #[tokio::test]
+  async fn test_resolved_layers_v1_1_1() {
+    let xml = r#"<WMT_MS_Capabilities version="1.1.1">
+      <Service><Name>OGC:WMS</Name><Title>Inheritance</Title></Service>
+      <Capability>
+        <Request>
+          <GetCapabilities><Format>application/vnd.ogc.wms_xml</Format></GetCapabilities>
+          <GetMap><Format>image/png</Format></GetMap>
+        </Request>
+        <Layer>
+          <Title>Root</Title>
+          <SRS>EPSG:4326</SRS>
+          <LatLonBoundingBox minx="-180" miny="-90" maxx="180" maxy="90"/>
+          <BoundingBox SRS="EPSG:4326" minx="-180" miny="-90" maxx="180" maxy="90"/>
+          <Dimension name="time" units="ISO8601"/>
+          <Extent name="time" default="2000">1990/2000/P1Y</Extent>
+          <Layer>
+            <Name>child</Name>
+            <Title>Child</Title>
+            <SRS>EPSG:26986</SRS>
+            <BoundingBox SRS="EPSG:26986" minx="0" miny="0" maxx="10" maxy="10"/>
+            <Extent name="time" default="1995">1995</Extent>
+          </Layer>
+        </Layer>
+      </Capability>
+    </WMT_MS_Capabilities>"#;
+    let capa = WebMappingService::from_string(xml.to_string())
+      .get_capabilities()
+      .await
+      .unwrap();
+    let layers = capa.resolved_layers();
+    assert_eq!(layers.len(), 1);
+    let child = &layers[0];
+    assert_eq!(child.crs().len(), 2);
+    assert!(child.ll_bbox.is_some());
+    let srs: Vec<&str> = child.bbox.iter().map(|b| b.srs.as_str()).collect();
+    assert_eq!(srs, vec!["EPSG:4326", "EPSG:26986"]);
+    let time = &child.dimensions()[0];
+    assert_eq!(time.units, "ISO8601");
+    assert_eq!(time.default, Some("1995".to_string()));
+    assert_eq!(time.values().unwrap(), vec!["1995"]);
+  }
+
+  #[tokio::test]
+  async fn test_bounding_boxes_v1_3_0() {
+    let xml = read_to_string("./examples/WMS-1.3.0.xml").unwrap();
+    let capa = WebMappingService::from_string(xml)
+      .get_capabilities()
+      .await
+      .unwrap();
+    let roads_rivers = capa.layer("ROADS_RIVERS").unwrap();
+    assert_eq!(roads_rivers.ll_bbox, None);
+    assert_eq!(
+      roads_rivers.geographic_bbox(),
+      Some(LatLonBoundingBox {
+        minx: -71.63,
+        miny: 41.75,
+        maxx: -70.78,
+        maxy: 42.90,
+      })
+    );
+    assert_eq!(
+      roads_rivers.bbox,
+      vec![
+        BoundingBox {
+          srs: "CRS:84".to_string(),
+          minx: -71.63,
+          miny: 41.75,
+          maxx: -70.78,
+          maxy: 42.90,
+          resx: Some(0.01),
+          resy: Some(0.01),
+        },
+        BoundingBox {
+          srs: "EPSG:26986".to_string(),
+          minx: 189000.0,
+          miny: 834000.0,
+          maxx: 285000.0,
+          maxy: 962000.0,
+          resx: Some(1.0),
+          resy: Some(1.0),
+        },
+      ]
+    );
+    // Inherited from ROADS_RIVERS
+    let resolved = capa.resolved_layers();
+    assert_eq!(
+      resolved[1].geographic_bbox(),
+      roads_rivers.geographic_bbox()
+    );
+    assert_eq!(resolved[1].bbox.len(), 2);
+  }
+
+  #[tokio::test]
+  async fn test_bounding_box_precision() {
+    let xml = read_to_string("./examples/WMS-1.1.1.xml").unwrap();
+    let capa = WebMappingService::from_string(xml)
+      .get_capabilities()
+      .await
+      .unwrap();
+    let layer = capa.layer("massgis:MASSNET.EXT_PT").unwrap();
+    assert_eq!(layer.geographic_bbox().unwrap().minx, -177.62118789510174);
+  }
+
+  #[tokio::test]
+  async fn test_layer_queries_v1_3_0() {
+    let xml = read_to_string("./examples/WMS-1.3.0.xml").unwrap();
+    let capa = WebMappingService::from_string(xml)
+      .get_capabilities()
+      .await
+      .unwrap();
+    let names =
+      |layers: Vec<&Layer>| -> Vec<String> { layers.into_iter().map(|l| l.name.clone()).collect() };
+    assert_eq!(
+      names(capa.layers().collect()),
+      vec![
+        "ROADS_RIVERS",
+        "ROADS_1M",
+        "RIVERS_1M",
+        "Clouds",
+        "Temperature",
+        "Pressure",
+        "ozone_image",
+        "population"
+      ]
+    );
+    assert_eq!(
+      names(capa.layers_breadth_first().collect()),
+      vec![
+        "ROADS_RIVERS",
+        "ozone_image",
+        "population",
+        "ROADS_1M",
+        "RIVERS_1M",
+        "Clouds",
+        "Temperature",
+        "Pressure"
+      ]
+    );
+    assert_eq!(
+      capa.layer("Pressure").unwrap().title,
+      "Forecast barometric pressure"
+    );
+    assert!(capa.layer("Unknown").is_none());
+    assert_eq!(
+      names(capa.layers().filter(|l| l.has_keyword("Canal")).collect()),
+      vec!["RIVERS_1M"]
+    );
+    assert_eq!(
+      names(capa.layers().filter(|l| l.queryable()).collect()),
+      vec!["ROADS_1M", "RIVERS_1M"]
+    );
+    assert_eq!(
+      names(
+        capa
+          .layers()
+          .filter(|l| l.supports_crs("epsg:26986"))
+          .collect()
+      ),
+      vec!["ROADS_RIVERS"]
+    );
+    let resolved = capa.resolved_layers();
+    assert_eq!(
+      resolved
+        .iter()
+        .filter(|l| l.supports_crs("EPSG:26986"))
+        .count(),
+      3
+    );
+  }
+
+  #[tokio::test]
+  async fn test_layer_queries_v1_1_1() {
+    let xml = read_to_string("./examples/WMS-1.1.1.xml").unwrap();
+    let capa = WebMappingService::from_string(xml)
+      .get_capabilities()
+      .await
+      .unwrap();
+    assert_eq!(capa.layers().count(), 1090);
+    assert_eq!(capa.layers_breadth_first().count(), 1090);
+    assert!(capa.layers().all(|l| l.queryable()));
+    let boston = LatLonBoundingBox {
+      minx: -71.2,
+      miny: 42.2,
+      maxx: -70.9,
+      maxy: 42.4,
+    };
+    let paris = LatLonBoundingBox {
+      minx: 2.2,
+      miny: 48.8,
+      maxx: 2.5,
+      maxy: 48.9,
+    };
+    assert_eq!(capa.layers().filter(|l| l.intersects(&boston)).count(), 876);
+    // A few layers have (mistakenly) near global extents
+    assert_eq!(capa.layers().filter(|l| l.intersects(&paris)).count(), 4);
+  }
+
+  #[tokio::test]
+  async fn test_layer_dimensions_v1_3_0() {
+    let xml = read_to_string("./examples/WMS-1.3.0.xml").unwrap();
+    let capa = WebMappingService::from_string(xml)
+      .get_capabilities()
+      .await
+      .unwrap();
+    let weather = &capa.capability.layer.unwrap().layers[1];
+    let time = &weather.dimensions()[0];
+    assert_eq!(time.name, "time");
+    assert_eq!(time.units, "ISO8601");
+    assert_eq!(time.default, Some("2000-08-22".to_string()));
+    assert_eq!(time.values().unwrap().len(), 600);
+
+    let pressure = weather.layers[2].dimensions();
+    assert_eq!(pressure.len(), 3);
+    assert_eq!(pressure[0].extent(), vec![]);
+    let elevation = &pressure[2];
+    assert_eq!(elevation.units, "CRS:88");
+    assert!(elevation.nearest_value);
+    assert!(!elevation.multiple_values);
+    assert_eq!(
+      elevation.values().unwrap(),
+      vec!["0", "1000", "3000", "5000", "10000"]
+    );
+  }
+
+  #[tokio::test]
+  async fn test_layer_dimensions_v1_1_1() {
+    let xml = r#"<WMT_MS_Capabilities version="1.1.1">
+      <Service><Name>OGC:WMS</Name><Title>Weather</Title></Service>
+      <Capability>
+        <Request>
+          <GetCapabilities><Format>application/vnd.ogc.wms_xml</Format></GetCapabilities>
+          <GetMap><Format>image/png</Format></GetMap>
+        </Request>
+        <Layer>
+          <Name>temperature</Name>
+          <Title>Temperature</Title>
+          <Dimension name="time" units="ISO8601"/>
+          <Dimension name="elevation" units="EPSG:5030" unitSymbol="m"/>
+          <Extent name="time" default="2000-08-22T12:00Z" multipleValues="1" current="true">
+            2000-08-22T00:00Z/2000-08-22T12:00Z/PT6H
+          </Extent>
+          <Extent name="elevation" default="0">0,100</Extent>
+        </Layer>
+      </Capability>
+    </WMT_MS_Capabilities>"#;
+    let capa = WebMappingService::from_string(xml.to_string())
+      .get_capabilities()
+      .await
+      .unwrap();
+    let dimensions = capa.capability.layer.unwrap().dimensions();
+    assert_eq!(dimensions.len(), 2);
+    let time = &dimensions[0];
+    assert_eq!(time.default, Some("2000-08-22T12:00Z".to_string()));
+    assert!(time.multiple_values);
+    assert!(time.current);
+    assert!(!time.nearest_value);
+    assert_eq!(
+      time.values().unwrap(),
+      vec![
+        "2000-08-22T00:00Z",
+        "2000-08-22T06:00Z",
+        "2000-08-22T12:00Z"
+      ]
+    );
+    assert_eq!(dimensions[1].unit_symbol, Some("m".to_string()));
+    assert_eq!(dimensions[1].values().unwrap(), vec!["0", "100"]);
+  }
+
   #[tokio::test]
   async fn test_basic_parse_v1_1_1() {
     let xml = read_to_string("./examples/WMS-1.1.1.xml").unwrap();
@@ -506,13 +2208,152 @@ mod tests {
         service_title: "Acme Corp. Map Server".to_string(),
         service_abstr: "Map Server maintained by Acme Corporation.  Contact: webmaster@wmt.acme.com.  High-quality maps showing roadrunner nests and possible ambush locations.".to_string(),
         inner_layers_len: 4,
-        skip_llbbox: true,
+        skip_llbbox: false,
         skip_layer_srs: true,
         skip_layer_bbox: true,
         skip_layer_list_name: true,
     });
   }
 
+  #[tokio::test]
+  async fn test_layer_metadata_v1_3_0() {
+    let xml = read_to_string("./examples/WMS-1.3.0.xml").unwrap();
+    let capa = WebMappingService::from_string(xml)
+      .get_capabilities()
+      .await
+      .unwrap();
+    let root = capa.capability.layer.as_ref().unwrap();
+    assert_eq!(root.authority_url.len(), 1);
+    assert_eq!(root.authority_url[0].name, "DIF_ID");
+    assert_eq!(
+      root.authority_url[0].online_resource.href,
+      "http://gcmd.gsfc.nasa.gov/difguide/whatisadif.html"
+    );
+
+    let roads_rivers = capa.layer("ROADS_RIVERS").unwrap();
+    let attribution = roads_rivers.attribution.as_ref().unwrap();
+    assert_eq!(
+      attribution.title,
+      Some("State College University".to_string())
+    );
+    assert_eq!(
+      attribution.online_resource.as_ref().unwrap().href,
+      "http://www.university.edu/"
+    );
+    let logo = attribution.logo_url.as_ref().unwrap();
+    assert_eq!((logo.width, logo.height), (100, 100));
+    assert_eq!(logo.format, "image/gif");
+    assert_eq!(
+      logo.online_resource.href,
+      "http://www.university.edu/icons/logo.gif"
+    );
+    assert_eq!(roads_rivers.identifiers.len(), 1);
+    assert_eq!(roads_rivers.identifiers[0].authority, "DIF_ID");
+    assert_eq!(roads_rivers.identifiers[0].value, "123456");
+    assert_eq!(roads_rivers.feature_list_url.len(), 1);
+    assert_eq!(
+      roads_rivers.feature_list_url[0].online_resource.href,
+      "http://www.university.edu/data/roads_rivers.gml"
+    );
+    assert!(roads_rivers.data_url.is_empty());
+
+    let roads = capa.layer("ROADS_1M").unwrap();
+    assert_eq!(roads.attribution, None);
+    let types: Vec<&str> = roads
+      .metadata_url
+      .iter()
+      .map(|m| m.metadata_type.as_str())
+      .collect();
+    assert_eq!(types, vec!["FGDC:1998", "ISO19115:2003"]);
+    assert_eq!(roads.metadata_url[1].format, "text/xml");
+    assert_eq!(
+      roads.metadata_url[1].online_resource.href,
+      "http://www.university.edu/metadata/roads.xml"
+    );
+
+    // Attribution and AuthorityURL are inherited, Identifier and MetadataURL are not
+    let resolved = capa.resolved_layers();
+    let rivers = resolved.iter().find(|l| l.name == "RIVERS_1M").unwrap();
+    assert_eq!(rivers.attribution.as_ref(), Some(attribution));
+    assert_eq!(rivers.authority_url, root.authority_url);
+    assert!(rivers.identifiers.is_empty());
+    assert!(rivers.metadata_url.is_empty());
+    assert!(rivers.feature_list_url.is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_service_metadata_v1_3_0() {
+    let xml = read_to_string("./examples/WMS-1.3.0.xml").unwrap();
+    let service = WebMappingService::from_string(xml)
+      .get_capabilities()
+      .await
+      .unwrap()
+      .service;
+    assert_eq!(
+      service.keyword_list.keyword,
+      vec!["bird", "roadrunner", "ambush"]
+    );
+    assert_eq!(service.online_resource.href, "http://hostname/");
+    assert_eq!(service.fees, Some("none".to_string()));
+    assert_eq!(service.access_constraints, Some("none".to_string()));
+    assert_eq!(service.layer_limit, Some(16));
+    assert_eq!(service.max_width, Some(2048));
+    let contact = service.contact_information.unwrap();
+    let person = contact.contact_person_primary.unwrap();
+    assert_eq!(person.contact_person, "Jeff Smith");
+    assert_eq!(person.contact_organization, "NASA");
+    assert_eq!(
+      contact.contact_position,
+      Some("Computer Scientist".to_string())
+    );
+    let address = contact.contact_address.unwrap();
+    assert_eq!(address.address_type, "postal");
+    assert_eq!(address.address, "NASA Goddard Space Flight Center");
+    assert_eq!(address.city, "Greenbelt");
+    assert_eq!(address.state_or_province, "MD");
+    assert_eq!(address.post_code, "20771");
+    assert_eq!(address.country, "USA");
+    assert_eq!(
+      contact.contact_voice_telephone,
+      Some("+1 301 555-1212".to_string())
+    );
+    assert_eq!(contact.contact_facsimile_telephone, None);
+    assert_eq!(
+      contact.contact_electronic_mail_address,
+      Some("user@host.com".to_string())
+    );
+  }
+
+  #[tokio::test]
+  async fn test_service_metadata_v1_1_1() {
+    let xml = read_to_string("./examples/WMS-1.1.1.xml").unwrap();
+    let service = WebMappingService::from_string(xml)
+      .get_capabilities()
+      .await
+      .unwrap()
+      .service;
+    assert_eq!(service.keyword_list.keyword.len(), 7);
+    assert_eq!(service.keyword_list.keyword[2].trim(), "Massachusetts");
+    assert_eq!(
+      service.online_resource.href,
+      "http://giswebservices.massgis.state.ma.us/geoserver/wms"
+    );
+    assert_eq!(service.fees, Some("NONE".to_string()));
+    assert_eq!(service.access_constraints, Some("NONE".to_string()));
+    assert_eq!(service.layer_limit, None);
+    let contact = service.contact_information.unwrap();
+    assert_eq!(
+      contact.contact_person_primary.unwrap().contact_person,
+      "Aleda Freeman"
+    );
+    assert_eq!(contact.contact_address.unwrap().post_code, "02114");
+    assert_eq!(contact.contact_facsimile_telephone.as_deref(), Some(""));
+    assert_eq!(
+      contact.contact_electronic_mail_address,
+      Some("aleda.freeman@state.ma.us".to_string())
+    );
+  }
+
   #[tokio::test]
   async fn test_get_crs_layer() {
     let xml = read_to_string("./examples/WMS-1.3.0.xml").unwrap();
@@ -540,7 +2381,386 @@ mod tests {
       miny: 41.75,
       maxx: -70.78,
       maxy: 42.90,
+      ..BoundingBox::default()
     };
     assert_eq!("-71.63,41.75,-70.78,42.9", bbox.to_str());
   }
+
+  #[test]
+  fn test_get_feature_info_url() {
+    let wms = WebMappingService::from_url("http://hostname/path?".to_string()).unwrap();
+    let mut params = GetFeatureInfoParameters {
+      map: GetMapParameters {
+        layers: vec!["ROADS_1M".to_string(), "RIVERS_1M".to_string()],
+        ..GetMapParameters::default()
+      },
+      query_layers: vec!["ROADS_1M".to_string(), "RIVERS_1M".to_string()],
+      info_format: "application/json".to_string(),
+      feature_count: Some(5),
+      i: 10,
+      j: 20,
+    };
+    let url = wms.get_feature_info_url(&params).unwrap();
+    let pairs: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+    let get = |k: &str| pairs.iter().find(|p| p.0 == k).map(|p| p.1.as_str());
+    assert_eq!(get("REQUEST"), Some("GetFeatureInfo"));
+    assert_eq!(get("LAYERS"), Some("ROADS_1M,RIVERS_1M"));
+    assert_eq!(get("QUERY_LAYERS"), Some("ROADS_1M,RIVERS_1M"));
+    assert_eq!(get("INFO_FORMAT"), Some("application/json"));
+    assert_eq!(get("FEATURE_COUNT"), Some("5"));
+    assert_eq!(get("I"), Some("10"));
+    assert_eq!(get("J"), Some("20"));
+    assert_eq!(get("X"), None);
+
+    params.map.version = "1.1.1".to_string();
+    params.feature_count = None;
+    let url = wms.get_feature_info_url(&params).unwrap();
+    let pairs: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+    let get = |k: &str| pairs.iter().find(|p| p.0 == k).map(|p| p.1.as_str());
+    assert_eq!(get("X"), Some("10"));
+    assert_eq!(get("Y"), Some("20"));
+    assert_eq!(get("I"), None);
+    assert_eq!(get("FEATURE_COUNT"), None);
+  }
+
+  #[tokio::test]
+  async fn test_parse_request_v1_3_0() {
+    let xml = read_to_string("./examples/WMS-1.3.0.xml").unwrap();
+    let mut wms_opt = WebMappingService::from_string(xml);
+    let request = wms_opt.get_capabilities().await.unwrap().capability.request;
+    assert_eq!(request.get_capabilities.formats, vec!["text/xml"]);
+    assert_eq!(
+      request.get_capabilities.get_url(),
+      Some("http://hostname/path?")
+    );
+    assert_eq!(
+      request.get_capabilities.post_url(),
+      Some("http://hostname/path?")
+    );
+    assert_eq!(
+      request.get_map.formats,
+      vec!["image/gif", "image/png", "image/jpeg"]
+    );
+    assert!(request.get_map.supports_format("image/png"));
+    assert!(!request.get_map.supports_format("image/tiff"));
+    assert_eq!(request.get_map.get_url(), Some("http://hostname/path?"));
+    assert_eq!(request.get_map.post_url(), None);
+    let gfi = request.get_feature_info.unwrap();
+    assert_eq!(gfi.formats, vec!["text/xml", "text/plain", "text/html"]);
+    assert_eq!(gfi.get_url(), Some("http://hostname/path?"));
+    assert!(request.get_legend_graphic.is_none());
+  }
+
+  #[tokio::test]
+  async fn test_parse_request_v1_1_1() {
+    let xml = read_to_string("./examples/WMS-1.1.1.xml").unwrap();
+    let mut wms_opt = WebMappingService::from_string(xml);
+    let request = wms_opt.get_capabilities().await.unwrap().capability.request;
+    let url = "http://giswebservices.massgis.state.ma.us/geoserver/wms?SERVICE=WMS&";
+    assert_eq!(request.get_capabilities.get_url(), Some(url));
+    assert_eq!(request.get_map.formats.len(), 40);
+    assert!(request.get_map.supports_format("image/png; mode=8bit"));
+    assert_eq!(request.get_map.get_url(), Some(url));
+    let gfi = request.get_feature_info.unwrap();
+    assert!(gfi.supports_format("application/vnd.ogc.gml"));
+    assert_eq!(gfi.post_url(), Some(url));
+    assert_eq!(request.describe_layer.unwrap().get_url(), Some(url));
+    assert_eq!(
+      request.get_legend_graphic.unwrap().formats,
+      vec!["image/png", "image/jpeg", "image/gif"]
+    );
+  }
+
+  #[tokio::test]
+  async fn test_operation_urls_from_capabilities() {
+    let xml = read_to_string("./examples/WMS-1.1.1.xml").unwrap();
+    let mut wms = WebMappingService::from_string(xml);
+    assert!(wms.get_map_url(&GetMapParameters::default()).is_err());
+
+    wms.get_capabilities().await.unwrap();
+    let params = GetMapParameters {
+      layers: vec!["massgis:GISDATA.TOWNS_POLYM".to_string()],
+      ..GetMapParameters::default()
+    };
+    let url = wms.get_map_url(&params).unwrap();
+    assert_eq!(url.host_str(), Some("giswebservices.massgis.state.ma.us"));
+    assert_eq!(url.path(), "/geoserver/wms");
+    let query = url.query().unwrap();
+    assert!(query.starts_with("REQUEST=GetMap&SERVICE=WMS&VERSION="));
+
+    let url = wms
+      .get_feature_info_url(&GetFeatureInfoParameters {
+        map: params,
+        ..GetFeatureInfoParameters::default()
+      })
+      .unwrap();
+    assert!(url
+      .query()
+      .unwrap()
+      .starts_with("REQUEST=GetFeatureInfo&SERVICE=WMS&"));
+  }
+
+  #[test]
+  fn test_operation_url_fallback() {
+    let wms =
+      WebMappingService::from_url("http://hostname/path?map=/tmp/a.map".to_string()).unwrap();
+    let url = wms
+      .get_map_url(&GetMapParameters {
+        layers: vec!["a".to_string()],
+        ..GetMapParameters::default()
+      })
+      .unwrap();
+    assert_eq!(url.path(), "/path");
+    assert!(url.query().unwrap().starts_with("REQUEST=GetMap&"));
+  }
+
+  fn query_value(url: &url::Url, key: &str) -> Option<String> {
+    url
+      .query_pairs()
+      .find(|(k, _)| k == key)
+      .map(|(_, v)| v.into_owned())
+  }
+
+  #[test]
+  fn test_is_lat_lon_crs() {
+    assert!(is_lat_lon_crs("EPSG:4326"));
+    assert!(is_lat_lon_crs("epsg:4258"));
+    assert!(is_lat_lon_crs("urn:ogc:def:crs:EPSG::4269"));
+    assert!(!is_lat_lon_crs("CRS:84"));
+    assert!(!is_lat_lon_crs("EPSG:26986"));
+    assert!(!is_lat_lon_crs("EPSG:3857"));
+    assert!(!is_lat_lon_crs("EPSG:4087"));
+    assert!(!is_lat_lon_crs("EPSG:4978"));
+    assert!(!is_lat_lon_crs("EPSG:4936"));
+    assert!(!is_lat_lon_crs("EPSG:4346"));
+    assert!(!is_lat_lon_crs("EPSG:4499"));
+    assert!(is_lat_lon_crs("EPSG:4979"));
+    assert!(is_lat_lon_crs("EPSG:4490"));
+    assert!(!is_lat_lon_crs("AUTO:42001"));
+  }
+
+  #[tokio::test]
+  async fn test_get_map_encoding_v1_1_1() {
+    let xml = read_to_string("./examples/WMS-1.1.1.xml").unwrap();
+    let mut wms = WebMappingService::from_string(xml);
+    let capa = wms.get_capabilities().await.unwrap();
+    let layer = &capa.capability.layer.unwrap().layers[0];
+    let bbox = layer.bbox[0].clone();
+    let ll = layer.ll_bbox.clone().unwrap();
+
+    let mut params = GetMapParameters {
+      version: "1.1.1".to_string(),
+      layers: vec![layer.name.clone()],
+      srs: bbox.srs.clone(),
+      bbox: bbox.clone(),
+      ..GetMapParameters::default()
+    };
+    let url = wms.get_map_url(&params).unwrap();
+    assert_eq!(query_value(&url, "SRS"), Some("EPSG:26986".to_string()));
+    assert_eq!(query_value(&url, "CRS"), None);
+    assert_eq!(query_value(&url, "BBOX"), Some(bbox.to_str()));
+    assert_eq!(query_value(&url, "BGCOLOR"), None);
+    assert_eq!(query_value(&url, "EXCEPTIONS"), None);
+
+    // 1.1.1 is always x/y, even for geographic systems
+    params.srs = "EPSG:4326".to_string();
+    params.bbox = BoundingBox {
+      srs: "EPSG:4326".to_string(),
+      minx: ll.minx,
+      miny: ll.miny,
+      maxx: ll.maxx,
+      maxy: ll.maxy,
+      ..BoundingBox::default()
+    };
+    let url = wms.get_map_url(&params).unwrap();
+    assert_eq!(
+      query_value(&url, "BBOX"),
+      Some(format!("{},{},{},{}", ll.minx, ll.miny, ll.maxx, ll.maxy))
+    );
+  }
+
+  #[tokio::test]
+  async fn test_get_map_encoding_v1_3_0() {
+    let xml = read_to_string("./examples/WMS-1.3.0.xml").unwrap();
+    let mut wms = WebMappingService::from_string(xml);
+    wms.get_capabilities().await.unwrap();
+    let mut params = GetMapParameters {
+      layers: vec!["ROADS_RIVERS".to_string()],
+      srs: "CRS:84".to_string(),
+      bbox: BoundingBox {
+        srs: "CRS:84".to_string(),
+        minx: -71.63,
+        miny: 41.75,
+        maxx: -70.78,
+        maxy: 42.90,
+        ..BoundingBox::default()
+      },
+      exceptions: Some("XML".to_string()),
+      ..GetMapParameters::default()
+    };
+    let url = wms.get_map_url(&params).unwrap();
+    assert_eq!(query_value(&url, "SERVICE"), Some("WMS".to_string()));
+    assert_eq!(query_value(&url, "CRS"), Some("CRS:84".to_string()));
+    assert_eq!(query_value(&url, "SRS"), None);
+    assert_eq!(
+      query_value(&url, "BBOX"),
+      Some("-71.63,41.75,-70.78,42.9".to_string())
+    );
+    assert_eq!(query_value(&url, "EXCEPTIONS"), Some("XML".to_string()));
+
+    params.srs = "EPSG:4326".to_string();
+    let url = wms.get_map_url(&params).unwrap();
+    assert_eq!(query_value(&url, "CRS"), Some("EPSG:4326".to_string()));
+    assert_eq!(
+      query_value(&url, "BBOX"),
+      Some("41.75,-71.63,42.9,-70.78".to_string())
+    );
+
+    params.srs = "EPSG:26986".to_string();
+    params.bbox = BoundingBox {
+      srs: "EPSG:26986".to_string(),
+      minx: 189000.0,
+      miny: 834000.0,
+      maxx: 285000.0,
+      maxy: 962000.0,
+      ..BoundingBox::default()
+    };
+    let url = wms.get_map_url(&params).unwrap();
+    assert_eq!(
+      query_value(&url, "BBOX"),
+      Some("189000,834000,285000,962000".to_string())
+    );
+  }
+
+  #[test]
+  fn test_get_map_layers_and_styles() {
+    let wms = WebMappingService::from_url("http://hostname/path".to_string()).unwrap();
+    let params = GetMapParameters::builder()
+      .layer("roads")
+      .styled_layer("rivers", "blue")
+      .layer("towns")
+      .bbox(BoundingBox {
+        srs: "EPSG:26986".to_string(),
+        ..BoundingBox::default()
+      })
+      .vendor("CQL_FILTER", "POP > 1000")
+      .vendor("viewparams", "low:2;high:5")
+      .build()
+      .unwrap();
+    assert_eq!(params.srs, "EPSG:26986");
+    let url = wms.get_map_url(&params).unwrap();
+    assert_eq!(
+      query_value(&url, "LAYERS"),
+      Some("roads,rivers,towns".to_string())
+    );
+    assert_eq!(query_value(&url, "STYLES"), Some(",blue,".to_string()));
+    assert_eq!(
+      query_value(&url, "CQL_FILTER"),
+      Some("POP > 1000".to_string())
+    );
+    assert_eq!(
+      query_value(&url, "viewparams"),
+      Some("low:2;high:5".to_string())
+    );
+    assert_eq!(query_value(&url, "SLD"), None);
+
+    // Default styles only are sent as an empty STYLES
+    let params = GetMapParameters::builder()
+      .layer("roads")
+      .layer("rivers")
+      .build()
+      .unwrap();
+    let url = wms.get_map_url(&params).unwrap();
+    assert_eq!(query_value(&url, "STYLES"), Some("".to_string()));
+
+    // An SLD may stand in for LAYERS and STYLES
+    let params = GetMapParameters::builder()
+      .sld_body("<StyledLayerDescriptor/>")
+      .sld_version("1.1.0")
+      .build()
+      .unwrap();
+    let url = wms.get_map_url(&params).unwrap();
+    assert_eq!(query_value(&url, "LAYERS"), None);
+    assert_eq!(query_value(&url, "STYLES"), None);
+    assert_eq!(
+      query_value(&url, "SLD_BODY"),
+      Some("<StyledLayerDescriptor/>".to_string())
+    );
+    assert_eq!(query_value(&url, "SLD_VERSION"), Some("1.1.0".to_string()));
+
+    assert!(GetMapParameters::builder().build().is_err());
+    assert!(GetMapParameters::builder()
+      .layer("roads")
+      .vendor("bbox", "0,0,1,1")
+      .build()
+      .is_err());
+    match wms.get_map_url(&GetMapParameters::default()) {
+      Err(Error::InvalidParameter(_)) => {}
+      other => panic!("Unexpected result: {:?}", other),
+    }
+    let params = GetMapParameters {
+      layers: vec!["roads".to_string(), "rivers".to_string()],
+      styles: vec!["blue".to_string()],
+      ..GetMapParameters::default()
+    };
+    assert!(wms.get_map_url(&params).is_err());
+  }
+
+  #[test]
+  fn test_from_url_proposed_version() {
+    let wms = WebMappingService::from_url("http://hostname/path?map=a.map".to_string()).unwrap();
+    assert_eq!(wms.version, "1.3.0");
+    let wms = WebMappingService::from_url(
+      "http://hostname/path?request=GetCapabilities&service=WMS&version=1.1.1".to_string(),
+    )
+    .unwrap();
+    assert_eq!(wms.version, "1.1.1");
+    assert_eq!(
+      wms.url.unwrap().query(),
+      Some("REQUEST=GetCapabilities&SERVICE=WMS")
+    );
+  }
+
+  #[tokio::test]
+  async fn test_negotiated_version_drives_requests() {
+    let xml = read_to_string("./examples/WMS-1.1.1.xml").unwrap();
+    let mut wms = WebMappingService::from_string(xml);
+    let params = GetMapParameters {
+      layers: vec!["massgis:GISDATA.TOWNS_POLYM".to_string()],
+      ..GetMapParameters::default()
+    };
+    let capa = wms.get_capabilities().await.unwrap();
+    assert_eq!(capa.version, "1.1.1");
+    assert_eq!(wms.version, "1.1.1");
+    let url = wms.get_map_url(&params).unwrap();
+    assert_eq!(query_value(&url, "VERSION"), Some("1.1.1".to_string()));
+    assert_eq!(query_value(&url, "SRS"), Some("CRS:84".to_string()));
+
+    let url = wms
+      .get_feature_info_url(&GetFeatureInfoParameters {
+        map: params,
+        i: 1,
+        j: 2,
+        ..GetFeatureInfoParameters::default()
+      })
+      .unwrap();
+    assert_eq!(query_value(&url, "VERSION"), Some("1.1.1".to_string()));
+    assert_eq!(query_value(&url, "X"), Some("1".to_string()));
+  }
+
+  #[tokio::test]
+  async fn test_get_capabilities_service_exception() {
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+      <ServiceExceptionReport version="1.3.0" xmlns="http://www.opengis.net/ogc">
+        <ServiceException code="InvalidUpdateSequence">Sequence is ahead</ServiceException>
+      </ServiceExceptionReport>"#;
+    let mut wms = WebMappingService::from_string(xml.to_string());
+    match wms.get_capabilities().await {
+      Err(Error::ServiceException(report)) => {
+        assert_eq!(report.code(), Some(&ExceptionCode::InvalidUpdateSequence));
+        assert_eq!(report.exceptions[0].message, "Sequence is ahead");
+      }
+      other => panic!("Expected a service exception, got {:?}", other),
+    }
+  }
 }
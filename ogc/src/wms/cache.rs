@@ -0,0 +1,392 @@
+//! Caching of capabilities documents between runs.
+//!
+//! Every document fetched by `WebMappingService` is kept as a `CachedCapabilities` along
+//! with its validators: the ETag and Last-Modified headers and the `updateSequence` of the
+//! document. While the Cache-Control `max-age` has not elapsed the document is used as is;
+//! afterwards it is revalidated with a conditional request carrying UPDATESEQUENCE, which
+//! the server may answer with 304 Not Modified or a `CurrentUpdateSequence` exception
+//! instead of the whole document. A response with `Cache-Control: no-store` is not cached.
+//! The cache can be saved to disk and given to a new service with
+//! `WebMappingService::with_cache`:
+//! ```no_run
+//! use ogc::wms::{CachedCapabilities, Wms, WebMappingService};
+//! #[tokio::main]
+//! async fn main() -> ogc::Result<()> {
+//!   let path = "/tmp/capabilities.json";
+//!   let mut wms = WebMappingService::from_url("https://ows.terrestris.de/osm/service".to_string())?;
+//!   if let Some(cache) = CachedCapabilities::load(path)? {
+//!     wms = wms.with_cache(cache);
+//!   }
+//!   let capa = wms.get_capabilities().await?;
+//!   if let Some(cache) = wms.cached_capabilities() {
+//!     cache.save(path)?;
+//!   }
+//!   Ok(())
+//! }
+//! ```
+use super::version;
+use crate::error::Error;
+use crate::http::HttpResponse;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A capabilities document and what is needed to revalidate it
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct CachedCapabilities {
+  /// The service URL the document was fetched from
+  pub url: String,
+  /// The negotiated version of the document
+  pub version: String,
+  pub xml: String,
+  /// The ETag header, sent back as If-None-Match
+  pub etag: Option<String>,
+  /// The Last-Modified header, sent back as If-Modified-Since
+  pub last_modified: Option<String>,
+  /// The `updateSequence` of the document, sent back as UPDATESEQUENCE
+  pub update_sequence: Option<String>,
+  /// When the document was fetched or last revalidated, in seconds since the Unix epoch
+  pub fetched_at: u64,
+  /// For how many seconds after `fetched_at` the document may be used without revalidation
+  pub max_age: u64,
+}
+
+impl CachedCapabilities {
+  /// Cache the document of a successful capabilities response, unless the response must not
+  /// be stored
+  pub(crate) fn new(
+    url: String,
+    version: String,
+    xml: String,
+    resp: &HttpResponse,
+  ) -> Option<Self> {
+    let cached = CachedCapabilities {
+      url,
+      version,
+      update_sequence: version::root_attribute(&xml, "updateSequence"),
+      xml,
+      ..CachedCapabilities::default()
+    };
+    cached.revalidated(resp)
+  }
+
+  /// The cache after the server confirmed the document is current, taking any new
+  /// validators and freshness from the response, or `None` if it must no longer be stored
+  pub(crate) fn revalidated(mut self, resp: &HttpResponse) -> Option<Self> {
+    let cache_control = resp.header("cache-control");
+    if cache_control.map_or(false, is_no_store) {
+      return None;
+    }
+    if let Some(etag) = resp.header("etag") {
+      self.etag = Some(etag.to_string());
+    }
+    if let Some(last_modified) = resp.header("last-modified") {
+      self.last_modified = Some(last_modified.to_string());
+    }
+    if let Some(cache_control) = cache_control {
+      self.max_age = max_age(cache_control);
+    }
+    self.fetched_at = now();
+    Some(self)
+  }
+
+  /// Whether the document may still be used without revalidating it
+  pub fn is_fresh(&self) -> bool {
+    now() < self.fetched_at.saturating_add(self.max_age)
+  }
+
+  /// The conditional request headers for revalidating the document
+  pub(crate) fn validators(&self) -> Vec<(String, String)> {
+    let mut headers = Vec::new();
+    if let Some(etag) = &self.etag {
+      headers.push(("If-None-Match".to_string(), etag.clone()));
+    }
+    if let Some(last_modified) = &self.last_modified {
+      headers.push(("If-Modified-Since".to_string(), last_modified.clone()));
+    }
+    headers
+  }
+
+  /// Read a cache saved with `save`, or `None` if there is no file at the path
+  pub fn load<P: AsRef<Path>>(path: P) -> crate::Result<Option<Self>> {
+    match fs::read_to_string(path) {
+      Ok(json) => Ok(Some(serde_json::from_str(&json)?)),
+      Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+      Err(e) => Err(Error::Io(e)),
+    }
+  }
+
+  /// Write the cache to the path, as JSON
+  pub fn save<P: AsRef<Path>>(&self, path: P) -> crate::Result<()> {
+    fs::write(path, serde_json::to_string(self)?)?;
+    Ok(())
+  }
+}
+
+/// The `max-age` of a Cache-Control header in seconds; `no-cache` means 0
+fn max_age(cache_control: &str) -> u64 {
+  let directives: Vec<String> = cache_control
+    .split(',')
+    .map(|d| d.trim().to_ascii_lowercase())
+    .collect();
+  if directives.iter().any(|d| d == "no-cache") {
+    return 0;
+  }
+  directives
+    .iter()
+    .find_map(|d| d.strip_prefix("max-age="))
+    .and_then(|age| age.trim_matches('"').parse().ok())
+    .unwrap_or(0)
+}
+
+/// Whether a Cache-Control header forbids storing the response
+fn is_no_store(cache_control: &str) -> bool {
+  cache_control
+    .split(',')
+    .any(|d| d.trim().eq_ignore_ascii_case("no-store"))
+}
+
+fn now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{max_age, CachedCapabilities};
+  use crate::http::{HttpResponse, MockClient};
+  use crate::wms::{ExceptionCode, WebMappingService, Wms};
+  use std::fs::read;
+
+  fn capabilities(headers: &[(&str, &str)]) -> HttpResponse {
+    let mut resp = HttpResponse::ok(
+      "application/vnd.ogc.wms_xml",
+      read("./examples/WMS-1.1.1.xml").unwrap(),
+    );
+    for (name, value) in headers {
+      resp.headers.push((name.to_string(), value.to_string()));
+    }
+    resp
+  }
+
+  fn not_modified() -> HttpResponse {
+    HttpResponse {
+      status: 304,
+      ..HttpResponse::default()
+    }
+  }
+
+  #[test]
+  fn test_max_age() {
+    assert_eq!(max_age("public, max-age=3600"), 3600);
+    assert_eq!(max_age("max-age=60, no-cache"), 0);
+    assert_eq!(max_age("no-store"), 0);
+    assert_eq!(max_age("private"), 0);
+  }
+
+  #[tokio::test]
+  async fn test_revalidation() {
+    let url = "http://hostname/wms".to_string();
+    let client = MockClient::default()
+      .with_response(
+        "GetCapabilities",
+        capabilities(&[("ETag", "\"v1\""), ("Cache-Control", "max-age=3600")]),
+      )
+      .with_response("GetCapabilities", not_modified());
+    let mut wms = WebMappingService::from_url_with_client(url.clone(), client.clone()).unwrap();
+    wms.get_capabilities().await.unwrap();
+    let cache = wms.cached_capabilities().unwrap().clone();
+    assert_eq!(
+      cache.url,
+      format!("{}?REQUEST=GetCapabilities&SERVICE=WMS", url)
+    );
+    assert_eq!(cache.version, "1.1.1");
+    assert_eq!(cache.etag, Some("\"v1\"".to_string()));
+    assert_eq!(cache.update_sequence, Some("24609".to_string()));
+    assert!(cache.is_fresh());
+
+    // A fresh cache is used without a request
+    let mut wms = WebMappingService::from_url_with_client(url.clone(), client.clone())
+      .unwrap()
+      .with_cache(cache.clone());
+    let capa = wms.get_capabilities().await.unwrap();
+    assert_eq!(capa.update_sequence, Some("24609".to_string()));
+    assert_eq!(wms.version, "1.1.1");
+    assert_eq!(client.requests().len(), 1);
+
+    // A stale one is revalidated
+    let stale = CachedCapabilities {
+      max_age: 0,
+      ..cache.clone()
+    };
+    let mut wms = WebMappingService::from_url_with_client(url.clone(), client.clone())
+      .unwrap()
+      .with_cache(stale);
+    let capa = wms.get_capabilities().await.unwrap();
+    assert_eq!(
+      capa.service.title,
+      "Massachusetts Data from MassGIS (GeoServer)"
+    );
+    let request = client.requests().pop().unwrap();
+    assert!(request
+      .query_pairs()
+      .any(|(k, v)| k == "UPDATESEQUENCE" && v == "24609"));
+    assert!(request
+      .query_pairs()
+      .any(|(k, v)| k == "VERSION" && v == "1.1.1"));
+    let headers = client.headers().pop().unwrap();
+    assert_eq!(
+      headers,
+      vec![("If-None-Match".to_string(), "\"v1\"".to_string())]
+    );
+    // The 304 carried no Cache-Control, so the document still has to be revalidated
+    let revalidated = wms.cached_capabilities().unwrap();
+    assert_eq!(revalidated.etag, Some("\"v1\"".to_string()));
+    assert!(!revalidated.is_fresh());
+
+    // A cache of another service is ignored
+    let wms = WebMappingService::from_url_with_client("http://other/wms".to_string(), client)
+      .unwrap()
+      .with_cache(cache);
+    assert_eq!(wms.cached_capabilities(), None);
+  }
+
+  #[tokio::test]
+  async fn test_stale_document_is_revalidated() {
+    let client = MockClient::default()
+      .with_response(
+        "GetCapabilities",
+        capabilities(&[("ETag", "\"v1\""), ("Cache-Control", "max-age=600")]),
+      )
+      .with_response("GetCapabilities", not_modified());
+    let mut wms =
+      WebMappingService::from_url_with_client("http://hostname/wms".to_string(), client.clone())
+        .unwrap();
+    wms.get_capabilities().await.unwrap();
+    wms.get_capabilities().await.unwrap();
+    assert_eq!(client.requests().len(), 1);
+
+    // Once max-age has passed the document in memory is revalidated too
+    wms.cache.as_mut().unwrap().fetched_at -= 601;
+    let capa = wms.get_capabilities().await.unwrap();
+    assert_eq!(capa.update_sequence, Some("24609".to_string()));
+    assert_eq!(client.requests().len(), 2);
+    assert_eq!(
+      client.headers().pop().unwrap(),
+      vec![("If-None-Match".to_string(), "\"v1\"".to_string())]
+    );
+    assert!(wms.cached_capabilities().unwrap().is_fresh());
+  }
+
+  #[tokio::test]
+  async fn test_failed_revalidation_keeps_cache() {
+    let url = "http://hostname/wms".to_string();
+    let client = MockClient::default()
+      .with_response(
+        "GetCapabilities",
+        HttpResponse {
+          status: 500,
+          ..HttpResponse::default()
+        },
+      )
+      .with_response("GetCapabilities", not_modified());
+    let mut wms = WebMappingService::from_url_with_client(url.clone(), client.clone()).unwrap();
+    let stale = CachedCapabilities::new(
+      format!("{}?REQUEST=GetCapabilities&SERVICE=WMS", url),
+      "1.1.1".to_string(),
+      capabilities(&[]).text(),
+      &capabilities(&[]),
+    )
+    .unwrap();
+    wms = wms.with_cache(stale.clone());
+    assert!(wms.get_capabilities().await.is_err());
+    assert_eq!(wms.cached_capabilities(), Some(&stale));
+
+    // so that it can still be revalidated later
+    let capa = wms.get_capabilities().await.unwrap();
+    assert_eq!(capa.update_sequence, Some("24609".to_string()));
+    assert_eq!(client.requests().len(), 2);
+  }
+
+  #[tokio::test]
+  async fn test_current_update_sequence_and_refresh() {
+    let exception = r#"<ServiceExceptionReport version="1.1.1">
+      <ServiceException code="CurrentUpdateSequence">The capabilities are current</ServiceException>
+    </ServiceExceptionReport>"#;
+    let client = MockClient::default()
+      .with_response(
+        "GetCapabilities",
+        capabilities(&[("Cache-Control", "max-age=600")]),
+      )
+      .with_response(
+        "GetCapabilities",
+        HttpResponse::ok("application/vnd.ogc.se_xml", exception.as_bytes().to_vec()),
+      )
+      .with_response(
+        "GetCapabilities",
+        HttpResponse::ok("text/xml", exception.as_bytes().to_vec()),
+      )
+      .with_response("GetCapabilities", not_modified());
+    let mut wms =
+      WebMappingService::from_url_with_client("http://hostname/wms".to_string(), client.clone())
+        .unwrap();
+    let capa = wms.get_capabilities().await.unwrap();
+    // Refreshing revalidates even a fresh document
+    assert_eq!(wms.refresh_capabilities().await.unwrap(), capa);
+    assert_eq!(client.requests().len(), 2);
+    assert!(wms.cached_capabilities().unwrap().is_fresh());
+
+    // Without a cache the exception is an error
+    let mut wms =
+      WebMappingService::from_url_with_client("http://hostname/wms".to_string(), client).unwrap();
+    match wms.get_capabilities().await {
+      Err(crate::Error::ServiceException(report)) => {
+        assert!(report.has_code(&ExceptionCode::CurrentUpdateSequence))
+      }
+      other => panic!("Unexpected result: {:?}", other),
+    }
+  }
+
+  #[tokio::test]
+  async fn test_no_store() {
+    let path = std::env::temp_dir().join(format!("ogc-no-store-{}.json", std::process::id()));
+    let client = MockClient::default()
+      .with_response(
+        "GetCapabilities",
+        capabilities(&[("ETag", "\"v1\""), ("Cache-Control", "private, no-store")]),
+      )
+      .with_response("GetCapabilities", capabilities(&[]));
+    let mut wms =
+      WebMappingService::from_url_with_client("http://hostname/wms".to_string(), client.clone())
+        .unwrap();
+    wms.get_capabilities().await.unwrap();
+    assert_eq!(wms.cached_capabilities(), None);
+    if let Some(cache) = wms.cached_capabilities() {
+      cache.save(&path).unwrap();
+    }
+    assert!(!path.exists());
+
+    // and the document is fetched again, unconditionally
+    wms.get_capabilities().await.unwrap();
+    assert_eq!(client.requests().len(), 2);
+    assert_eq!(client.headers().pop().unwrap(), vec![]);
+  }
+
+  #[test]
+  fn test_save_and_load() {
+    let path = std::env::temp_dir().join(format!("ogc-cache-{}.json", std::process::id()));
+    assert_eq!(CachedCapabilities::load(&path).unwrap(), None);
+    let cache = CachedCapabilities {
+      url: "http://hostname/wms".to_string(),
+      version: "1.3.0".to_string(),
+      xml: "<WMS_Capabilities/>".to_string(),
+      last_modified: Some("Wed, 21 Oct 2015 07:28:00 GMT".to_string()),
+      ..CachedCapabilities::default()
+    };
+    cache.save(&path).unwrap();
+    assert_eq!(CachedCapabilities::load(&path).unwrap(), Some(cache));
+    std::fs::remove_file(&path).unwrap();
+  }
+}
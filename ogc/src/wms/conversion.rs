@@ -0,0 +1,375 @@
+//! Conversion of capabilities between WMS 1.1.x and 1.3.0, e.g. to front a 1.1.1 server for
+//! clients that only speak 1.3.0.
+//!
+//! The layers' `SRS` become `CRS` and `LatLonBoundingBox` becomes `EX_GeographicBoundingBox`
+//! (and vice versa), with the bounding boxes of geographic CRSs swapped to the axis order of
+//! the version. A `ScaleHint` becomes a pair of scale denominators and `Extent` elements are
+//! folded into their `Dimension`. The exception and capabilities formats get the names the
+//! version uses, e.g. `application/vnd.ogc.se_xml` for the 1.3.0 `XML`.
+use super::{
+  is_lat_lon_crs, is_v1_3, BoundingBox, Dimension, ExGeographicBoundingBox, Extent,
+  GetCapabilities, LatLonBoundingBox, Layer, ScaleHint, SUPPORTED_VERSIONS,
+};
+use crate::error::Error;
+use std::mem::take;
+
+/// The 1.1.x exception formats and their 1.3.0 names
+const EXCEPTION_FORMATS: [(&str, &str); 3] = [
+  ("application/vnd.ogc.se_xml", "XML"),
+  ("application/vnd.ogc.se_inimage", "INIMAGE"),
+  ("application/vnd.ogc.se_blank", "BLANK"),
+];
+
+/// The 1.1.x capabilities format and its 1.3.0 name
+const CAPABILITIES_FORMATS: [(&str, &str); 1] = [("application/vnd.ogc.wms_xml", "text/xml")];
+
+impl GetCapabilities {
+  /// The capabilities as a document of another of the `SUPPORTED_VERSIONS`, e.g. to
+  /// republish them with `to_xml`. Elements the target version has no counterpart for, such
+  /// as the 1.3.0 `LayerLimit` in 1.1.x, are dropped.
+  pub fn to_version(&self, version: &str) -> crate::Result<GetCapabilities> {
+    if !SUPPORTED_VERSIONS.contains(&version) {
+      return Err(Error::InvalidParameter(format!(
+        "Cannot convert capabilities to version {:?}",
+        version
+      )));
+    }
+    let mut capa = self.clone();
+    capa.version = version.to_string();
+    let upgrade = is_v1_3(version);
+    if is_v1_3(&self.version) == upgrade {
+      return Ok(capa);
+    }
+
+    let service = &mut capa.service;
+    service.name = if upgrade { "WMS" } else { "OGC:WMS" }.to_string();
+    if !upgrade {
+      service.layer_limit = None;
+      service.max_width = None;
+      service.max_height = None;
+    }
+    let capability = &mut capa.capability;
+    rename(
+      &mut capability.exception.formats,
+      &EXCEPTION_FORMATS,
+      upgrade,
+    );
+    rename(
+      &mut capability.request.get_capabilities.formats,
+      &CAPABILITIES_FORMATS,
+      upgrade,
+    );
+    if let Some(layer) = &mut capability.layer {
+      if upgrade {
+        upgrade_layer(layer, &[]);
+      } else {
+        downgrade_layer(layer, &[], (None, None));
+      }
+    }
+    Ok(capa)
+  }
+}
+
+/// Replace the formats by their names in the other version, dropping any duplicates this
+/// gives, e.g. of a 1.1.1 GetCapabilities offering both its own format and `text/xml`
+fn rename(formats: &mut Vec<String>, names: &[(&str, &str)], upgrade: bool) {
+  let mut renamed: Vec<String> = Vec::new();
+  for format in formats.drain(..) {
+    let format = names
+      .iter()
+      .find_map(|(v1_1, v1_3)| match upgrade {
+        true if format == *v1_1 => Some(v1_3.to_string()),
+        false if format == *v1_3 => Some(v1_1.to_string()),
+        _ => None,
+      })
+      .unwrap_or(format);
+    if !renamed.contains(&format) {
+      renamed.push(format);
+    }
+  }
+  *formats = renamed;
+}
+
+/// Swap the axes of a bounding box of a geographic CRS, whose axis order is latitude first
+/// in 1.3.0 and longitude first in 1.1.x. Geocentric and projected CRSs keep their axes.
+fn swap_geographic_axes(bbox: &mut BoundingBox) {
+  if is_lat_lon_crs(&bbox.srs) {
+    std::mem::swap(&mut bbox.minx, &mut bbox.miny);
+    std::mem::swap(&mut bbox.maxx, &mut bbox.maxy);
+    std::mem::swap(&mut bbox.resx, &mut bbox.resy);
+  }
+}
+
+/// Convert a 1.1.x Layer tree, given the Dimensions declared by the Layer's ancestors whose
+/// Extent the Layer may give. A Dimension whose Extent is only given by descendants is left
+/// to them, as a 1.3.0 Dimension without extent is invalid.
+fn upgrade_layer(layer: &mut Layer, inherited: &[Dimension]) {
+  let srs: Vec<String> = layer.srs.drain().collect();
+  layer.crs.extend(srs);
+  if let Some(ll_bbox) = layer.ll_bbox.take() {
+    layer
+      .ex_bbox
+      .get_or_insert_with(|| ExGeographicBoundingBox::from(&ll_bbox));
+  }
+  layer.bbox.iter_mut().for_each(swap_geographic_axes);
+  let (min, max) = layer.scale_denominators();
+  layer.min_scale_denominator = min;
+  layer.max_scale_denominator = max;
+  layer.scale_hint = None;
+
+  let declared = layer.dimension.clone();
+  let mut dimensions = layer.dimensions();
+  for extent in take(&mut layer.extent) {
+    if declared.iter().any(|d| d.name == extent.name) {
+      continue;
+    }
+    if let Some(dimension) = inherited.iter().find(|d| d.name == extent.name) {
+      dimensions.push(dimension.clone().with_extent(&extent));
+    }
+  }
+  dimensions.retain(|d| !d.extent.trim().is_empty());
+  layer.dimension = dimensions;
+  for style in layer.styles.iter_mut() {
+    style.style_url = None;
+  }
+
+  let mut inherited = inherited.to_vec();
+  inherited.extend(declared);
+  for child in layer.layers.iter_mut() {
+    upgrade_layer(child, &inherited);
+  }
+}
+
+/// Convert a 1.3.0 Layer tree, given the names of the Dimensions declared by the Layer's
+/// ancestors and the nearest scale denominators they give. A 1.1.x ScaleHint is inherited
+/// as a whole, so a Layer giving one scale denominator takes the other from its ancestors,
+/// and a Dimension is only declared where it first appears, with an Extent wherever it is
+/// given.
+fn downgrade_layer(layer: &mut Layer, declared: &[String], scale: (Option<f64>, Option<f64>)) {
+  let crs: Vec<String> = layer.crs.drain().collect();
+  layer.srs.extend(crs);
+  if let Some(ex_bbox) = layer.ex_bbox.take() {
+    layer
+      .ll_bbox
+      .get_or_insert_with(|| LatLonBoundingBox::from(&ex_bbox));
+  }
+  layer.bbox.iter_mut().for_each(swap_geographic_axes);
+  let (min, max) = (
+    layer.min_scale_denominator.take(),
+    layer.max_scale_denominator.take(),
+  );
+  if min.is_some() || max.is_some() {
+    layer.scale_hint = Some(ScaleHint::from_scale_denominators(
+      min.or(scale.0),
+      max.or(scale.1),
+    ));
+  }
+  let scale = (min.or(scale.0), max.or(scale.1));
+
+  let mut declared = declared.to_vec();
+  for dimension in take(&mut layer.dimension) {
+    if !dimension.extent.trim().is_empty() {
+      layer.extent.push(Extent {
+        name: dimension.name.clone(),
+        default: dimension.default,
+        multiple_values: dimension.multiple_values,
+        nearest_value: dimension.nearest_value,
+        current: dimension.current,
+        value: dimension.extent,
+      });
+    }
+    if !declared.contains(&dimension.name) {
+      declared.push(dimension.name.clone());
+      layer.dimension.push(Dimension {
+        name: dimension.name,
+        units: dimension.units,
+        unit_symbol: dimension.unit_symbol,
+        ..Dimension::default()
+      });
+    }
+  }
+
+  for child in layer.layers.iter_mut() {
+    downgrade_layer(child, &declared, scale);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::swap_geographic_axes;
+  use crate::wms::tests::{example_capabilities, parse_capabilities};
+  use crate::wms::BoundingBox;
+
+  #[test]
+  fn test_swap_geographic_axes() {
+    let bbox = |srs: &str| BoundingBox {
+      srs: srs.to_string(),
+      minx: 1.0,
+      miny: 2.0,
+      maxx: 3.0,
+      maxy: 4.0,
+      ..BoundingBox::default()
+    };
+    let mut swapped = bbox("EPSG:4326");
+    swap_geographic_axes(&mut swapped);
+    assert_eq!(
+      (swapped.minx, swapped.miny, swapped.maxx, swapped.maxy),
+      (2.0, 1.0, 4.0, 3.0)
+    );
+    for srs in ["EPSG:4978", "EPSG:26986", "CRS:84"] {
+      let mut kept = bbox(srs);
+      swap_geographic_axes(&mut kept);
+      assert_eq!(kept, bbox(srs));
+    }
+  }
+
+  #[tokio::test]
+  async fn test_upgrade() {
+    let capa = example_capabilities(
+      "1.1.1",
+      &[
+        (
+          "<!--All supported EPSG projections:-->",
+          "<Dimension name=\"time\" units=\"ISO8601\"/>",
+        ),
+        (
+          "<Abstract>Layer-Group type layer: massgis_dep_21e_mcp</Abstract>",
+          "<Abstract>Layer-Group type layer: massgis_dep_21e_mcp</Abstract>\
+           <Extent name=\"time\" default=\"2000\">1990/2000/P1Y</Extent>",
+        ),
+      ],
+    )
+    .await;
+    let upgraded = capa.to_version("1.3.0").unwrap();
+    assert_eq!(upgraded.version, "1.3.0");
+    assert_eq!(upgraded.service.name, "WMS");
+    assert_eq!(
+      upgraded.capability.exception.formats[..4],
+      ["XML", "INIMAGE", "BLANK", "application/json"]
+    );
+    assert_eq!(
+      upgraded.capability.request.get_capabilities.formats,
+      vec!["text/xml"]
+    );
+
+    let original = capa.layer("massgis_dep_21e_mcp").unwrap();
+    let layer = upgraded.layer("massgis_dep_21e_mcp").unwrap();
+    assert_eq!(layer.crs(), original.crs());
+    assert!(layer.supports_crs("EPSG:26986"));
+    assert_eq!(layer.ll_bbox, None);
+    assert_eq!(layer.geographic_bbox(), original.geographic_bbox());
+    assert_eq!(layer.scale_hint, None);
+    assert_eq!(layer.scale_denominators(), original.scale_denominators());
+
+    // The time Dimension moves to the Layer giving its Extent
+    let root = upgraded.capability.layer.as_ref().unwrap();
+    assert!(root.dimension.is_empty());
+    let time = &layer.dimensions()[0];
+    assert_eq!(time.name, "time");
+    assert_eq!(time.units, "ISO8601");
+    assert_eq!(time.extent, "1990/2000/P1Y");
+    assert_eq!(time.default.as_deref(), Some("2000"));
+
+    // The document written is the converted one
+    let xml = upgraded.to_xml().unwrap();
+    assert!(xml.contains("<WMS_Capabilities"));
+    assert!(!xml.contains("<SRS>"));
+    assert_eq!(parse_capabilities(&xml).await, upgraded);
+  }
+
+  #[tokio::test]
+  async fn test_downgrade() {
+    let capa = example_capabilities(
+      "1.3.0",
+      &[
+        (
+          "<Name>ROADS_RIVERS</Name>",
+          "<Name>ROADS_RIVERS</Name><MinScaleDenominator>1000</MinScaleDenominator>",
+        ),
+        (
+          "<Name>ROADS_1M</Name>",
+          "<Name>ROADS_1M</Name><MaxScaleDenominator>100000</MaxScaleDenominator>",
+        ),
+        (
+          "<Name>RIVERS_1M</Name>",
+          "<Name>RIVERS_1M</Name><MinScaleDenominator>5000</MinScaleDenominator>",
+        ),
+        ("CRS=\"CRS:84\"", "CRS=\"EPSG:4326\""),
+      ],
+    )
+    .await;
+    let downgraded = capa.to_version("1.1.1").unwrap();
+    assert_eq!(downgraded.service.name, "OGC:WMS");
+    assert_eq!(downgraded.service.max_width, None);
+    assert_eq!(
+      downgraded.capability.exception.formats,
+      vec![
+        "application/vnd.ogc.se_xml",
+        "application/vnd.ogc.se_inimage",
+        "application/vnd.ogc.se_blank"
+      ]
+    );
+
+    // EPSG:4326 is longitude first in 1.1.1
+    let roads = downgraded.layer("ROADS_RIVERS").unwrap();
+    assert_eq!(roads.ex_bbox, None);
+    assert_eq!(
+      roads.geographic_bbox(),
+      capa.layer("ROADS_RIVERS").unwrap().geographic_bbox()
+    );
+    assert_eq!(
+      roads.bbox[0],
+      BoundingBox {
+        srs: "EPSG:4326".to_string(),
+        minx: 41.75,
+        miny: -71.63,
+        maxx: 42.9,
+        maxy: -70.78,
+        resx: Some(0.01),
+        resy: Some(0.01),
+      }
+    );
+
+    // A ScaleHint replaces the inherited one, so it takes the bound a Layer does not give
+    // from its ancestors, and an unbounded maximum is not written as 0
+    let roads = downgraded.layer("ROADS_1M").unwrap();
+    assert_eq!(roads.min_scale_denominator, None);
+    let (min, max) = roads.scale_denominators();
+    assert!((min.unwrap() - 1000.0).abs() < 1e-6);
+    assert!((max.unwrap() - 100_000.0).abs() < 1e-6);
+    let rivers = downgraded.layer("RIVERS_1M").unwrap();
+    assert!(rivers.scale_hint.as_ref().unwrap().max > 0.0);
+    let (min, max) = rivers.scale_denominators();
+    assert!((min.unwrap() - 5000.0).abs() < 1e-6);
+    assert_eq!(max, None);
+
+    // The Dimensions are split into Dimension and Extent elements
+    for name in ["Clouds", "Temperature"] {
+      assert_eq!(
+        downgraded.layer(name).unwrap().dimensions(),
+        capa.layer(name).unwrap().dimensions()
+      );
+    }
+    // A Dimension declared by an ancestor is not declared again, only its Extent given
+    let pressure = downgraded.layer("Pressure").unwrap();
+    let dimensions: Vec<&str> = pressure.dimension.iter().map(|d| d.name.as_str()).collect();
+    assert_eq!(dimensions, ["elevation"]);
+    let extents: Vec<&str> = pressure.extent.iter().map(|e| e.name.as_str()).collect();
+    assert_eq!(extents, ["time", "elevation"]);
+    let xml = downgraded.to_xml().unwrap();
+    assert!(xml.contains("<Extent name=\"time\" default=\"2000\">1990/2000/P1Y</Extent>"));
+    assert_eq!(parse_capabilities(&xml).await, downgraded);
+
+    // and joined again
+    let upgraded = downgraded.to_version("1.3.0").unwrap();
+    assert_eq!(
+      upgraded.layer("Temperature").unwrap().dimensions(),
+      capa.layer("Temperature").unwrap().dimensions()
+    );
+    assert_eq!(
+      upgraded.layer("ROADS_RIVERS").unwrap().bbox,
+      capa.layer("ROADS_RIVERS").unwrap().bbox
+    );
+    assert!(capa.to_version("2.0.0").is_err());
+  }
+}
@@ -0,0 +1,387 @@
+//! Sample dimensions of a layer, such as time or elevation.
+//!
+//! WMS 1.3.0 declares a dimension and its extent in one `Dimension` element, while 1.1.x
+//! declares the units in `Dimension` and the values in a separate `Extent` element of the
+//! same name. Either way `Layer::dimensions` yields one `Dimension` per axis.
+use super::{iso8601, xml_bool};
+use crate::error::Error;
+use std::cmp::Ordering;
+
+/// The most values `Dimension::values` and `ExtentValue::values` enumerate, as an extent such
+/// as every second of a decade would not fit in memory
+pub const MAX_EXTENT_VALUES: usize = 100_000;
+
+/// A sample dimension of a layer, e.g. `time` or `elevation`
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Dimension {
+  /// The dimension name, e.g. `time`
+  pub name: String,
+  /// The units of the values, e.g. `ISO8601` or `EPSG:5030`
+  #[serde(default)]
+  pub units: String,
+  /// The symbol of the units, e.g. `m`
+  #[serde(rename = "unitSymbol", default)]
+  pub unit_symbol: Option<String>,
+  /// The value used when a request does not give one
+  #[serde(default)]
+  pub default: Option<String>,
+  /// Whether a request may ask for several values
+  #[serde(rename = "multipleValues", default, deserialize_with = "xml_bool")]
+  pub multiple_values: bool,
+  /// Whether the server rounds a requested value to the nearest valid one
+  #[serde(rename = "nearestValue", default, deserialize_with = "xml_bool")]
+  pub nearest_value: bool,
+  /// Whether `current` is a valid time value, meaning the most recent one
+  #[serde(default, deserialize_with = "xml_bool")]
+  pub current: bool,
+  /// The valid values as written by the server, e.g. `1999-01-01/2000-08-22/P1D`
+  #[serde(rename = "$value", default)]
+  pub extent: String,
+}
+
+impl Dimension {
+  /// The values and intervals of the extent
+  pub fn extent(&self) -> Vec<ExtentValue> {
+    self
+      .extent
+      .split(',')
+      .map(str::trim)
+      .filter(|v| !v.is_empty())
+      .map(ExtentValue::parse)
+      .collect()
+  }
+
+  /// Every valid value, enumerating the intervals of the extent; there may be at most
+  /// `MAX_EXTENT_VALUES`
+  pub fn values(&self) -> crate::Result<Vec<String>> {
+    let mut values = Vec::new();
+    for extent in self.extent() {
+      values.extend(extent.values()?);
+      if values.len() > MAX_EXTENT_VALUES {
+        return Err(too_many_values(&self.extent));
+      }
+    }
+    Ok(values)
+  }
+
+  /// Whether the requested value, as given in a TIME, ELEVATION or DIM_ parameter, is within
+  /// the extent. The value may be a list and its items ranges (`min/max`), each end of which
+  /// must fall within the extent; `current` is accepted when the dimension allows it.
+  pub fn contains(&self, value: &str) -> bool {
+    let extent = self.extent();
+    value.split(',').map(str::trim).all(|item| {
+      item.split('/').take(2).all(|v| {
+        (self.current && v.eq_ignore_ascii_case("current"))
+          || extent.is_empty()
+          || extent.iter().any(|e| e.contains(v))
+      })
+    })
+  }
+
+  /// Complete this 1.1.x dimension with the values and options of its `Extent`
+  pub(crate) fn with_extent(mut self, extent: &Extent) -> Self {
+    self.default = extent.default.clone().or(self.default);
+    self.multiple_values |= extent.multiple_values;
+    self.nearest_value |= extent.nearest_value;
+    self.current |= extent.current;
+    if self.extent.trim().is_empty() {
+      self.extent = extent.value.clone();
+    }
+    self
+  }
+}
+
+/// The 1.1.x `Extent` element, giving the values of the `Dimension` with the same name
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub(crate) struct Extent {
+  pub name: String,
+  #[serde(default)]
+  pub default: Option<String>,
+  #[serde(rename = "multipleValues", default, deserialize_with = "xml_bool")]
+  pub multiple_values: bool,
+  #[serde(rename = "nearestValue", default, deserialize_with = "xml_bool")]
+  pub nearest_value: bool,
+  #[serde(default, deserialize_with = "xml_bool")]
+  pub current: bool,
+  #[serde(rename = "$value", default)]
+  pub value: String,
+}
+
+/// An entry of a dimension's extent, as per Annex C of the WMS 1.3.0 specification
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum ExtentValue {
+  /// A single value, e.g. `2000-08-22` or `1000`
+  Single(String),
+  /// The values from `min` to `max` inclusive, `resolution` apart; without a resolution
+  /// any value within the interval is valid
+  Interval {
+    min: String,
+    max: String,
+    resolution: Option<String>,
+  },
+}
+
+impl ExtentValue {
+  /// Parse `value` or `min/max/resolution`, where a resolution of `0` (or none) means a
+  /// continuous interval
+  pub fn parse(s: &str) -> Self {
+    let parts: Vec<&str> = s.split('/').map(str::trim).collect();
+    match parts.as_slice() {
+      [min, max] => ExtentValue::Interval {
+        min: min.to_string(),
+        max: max.to_string(),
+        resolution: None,
+      },
+      [min, max, resolution] => ExtentValue::Interval {
+        min: min.to_string(),
+        max: max.to_string(),
+        resolution: Some(resolution.to_string()).filter(|r| r.parse::<f64>() != Ok(0.0)),
+      },
+      _ => ExtentValue::Single(s.trim().to_string()),
+    }
+  }
+
+  /// Whether the value equals this single value or falls within this interval, on its
+  /// resolution grid if it has one
+  pub fn contains(&self, value: &str) -> bool {
+    match self {
+      ExtentValue::Single(single) => {
+        single == value || compare(single, value) == Some(Ordering::Equal)
+      }
+      ExtentValue::Interval {
+        min,
+        max,
+        resolution,
+      } => {
+        compare(min, value).map_or(false, |o| o != Ordering::Greater)
+          && compare(value, max).map_or(false, |o| o != Ordering::Greater)
+          && resolution
+            .as_ref()
+            .map_or(true, |r| is_on_grid(min, value, r))
+      }
+    }
+  }
+
+  /// The values of this entry. Intervals with an ISO 8601 period resolution (e.g. `P1D`) are
+  /// enumerated as times, others as numbers; continuous intervals cannot be enumerated, nor
+  /// intervals of more than `MAX_EXTENT_VALUES` values.
+  pub fn values(&self) -> crate::Result<Vec<String>> {
+    match self {
+      ExtentValue::Single(value) => Ok(vec![value.clone()]),
+      ExtentValue::Interval {
+        min,
+        max,
+        resolution: None,
+      } => Err(Error::InvalidParameter(format!(
+        "The continuous interval {}/{} cannot be enumerated",
+        min, max
+      ))),
+      ExtentValue::Interval {
+        min,
+        max,
+        resolution: Some(resolution),
+      } if resolution.starts_with('P') => {
+        iso8601::enumerate(min, max, resolution, MAX_EXTENT_VALUES)
+      }
+      ExtentValue::Interval {
+        min,
+        max,
+        resolution: Some(resolution),
+      } => {
+        let number = |s: &str| {
+          s.parse::<f64>()
+            .map_err(|_| Error::InvalidParameter(format!("Invalid extent number: {:?}", s)))
+        };
+        let (min, max, step) = (number(min)?, number(max)?, number(resolution)?);
+        if step <= 0.0 {
+          return Err(Error::InvalidParameter(format!(
+            "Invalid extent resolution: {}",
+            resolution
+          )));
+        }
+        // Tolerate rounding errors when the resolution does not divide the range exactly
+        let count = ((max - min) / step + 1e-9).floor() as i64 + 1;
+        if count > MAX_EXTENT_VALUES as i64 {
+          return Err(too_many_values(&format!("{}/{}/{}", min, max, step)));
+        }
+        Ok(
+          (0..count.max(0))
+            .map(|i| ((min + i as f64 * step) * 1e9).round() / 1e9)
+            .map(|v| v.to_string())
+            .collect(),
+        )
+      }
+    }
+  }
+}
+
+fn too_many_values(extent: &str) -> Error {
+  Error::InvalidParameter(format!(
+    "The extent {} has more than {} values",
+    extent.trim(),
+    MAX_EXTENT_VALUES
+  ))
+}
+
+/// Whether the value is `min` plus a whole number of `resolution`s, which is a number or an
+/// ISO 8601 period
+fn is_on_grid(min: &str, value: &str, resolution: &str) -> bool {
+  if resolution.starts_with('P') {
+    return iso8601::is_on_grid(min, value, resolution).unwrap_or(false);
+  }
+  match (
+    min.trim().parse::<f64>(),
+    value.trim().parse::<f64>(),
+    resolution.trim().parse::<f64>(),
+  ) {
+    (Ok(min), Ok(value), Ok(step)) if step > 0.0 => {
+      let times = (value - min) / step;
+      // Tolerate rounding errors, as `values` does
+      (times - times.round()).abs() < 1e-9 * times.abs().max(1.0)
+    }
+    _ => false,
+  }
+}
+
+/// Compare two values of an extent, as numbers or else as ISO 8601 instants
+fn compare(a: &str, b: &str) -> Option<Ordering> {
+  match (a.trim().parse::<f64>(), b.trim().parse::<f64>()) {
+    (Ok(a), Ok(b)) => a.partial_cmp(&b),
+    _ => Some(iso8601::epoch_millis(a)?.cmp(&iso8601::epoch_millis(b)?)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Dimension, ExtentValue, MAX_EXTENT_VALUES};
+
+  #[test]
+  fn test_parse_extent() {
+    let dimension = Dimension {
+      extent: " 1990/1995/P1Y, 2000, 2005/2010/0, 2020/2030 ".to_string(),
+      ..Dimension::default()
+    };
+    assert_eq!(
+      dimension.extent(),
+      vec![
+        ExtentValue::Interval {
+          min: "1990".to_string(),
+          max: "1995".to_string(),
+          resolution: Some("P1Y".to_string()),
+        },
+        ExtentValue::Single("2000".to_string()),
+        ExtentValue::Interval {
+          min: "2005".to_string(),
+          max: "2010".to_string(),
+          resolution: None,
+        },
+        ExtentValue::Interval {
+          min: "2020".to_string(),
+          max: "2030".to_string(),
+          resolution: None,
+        },
+      ]
+    );
+    assert!(dimension.values().is_err());
+    assert!(Dimension::default().extent().is_empty());
+  }
+
+  #[test]
+  fn test_too_many_values() {
+    assert!(ExtentValue::parse("0/1e12/1").values().is_err());
+    assert!(ExtentValue::parse("1970-01-01/2030-01-01/PT1S")
+      .values()
+      .is_err());
+    assert_eq!(
+      ExtentValue::parse("1/100000/1").values().unwrap().len(),
+      MAX_EXTENT_VALUES
+    );
+    // Nor may the entries together exceed the maximum
+    let dimension = Dimension {
+      extent: "1/100000/1,100001".to_string(),
+      ..Dimension::default()
+    };
+    assert!(dimension.values().is_err());
+  }
+
+  #[test]
+  fn test_values() {
+    let dimension = Dimension {
+      extent: "1990/1993/P1Y,2000".to_string(),
+      ..Dimension::default()
+    };
+    assert_eq!(
+      dimension.values().unwrap(),
+      vec!["1990", "1991", "1992", "1993", "2000"]
+    );
+    assert_eq!(
+      ExtentValue::parse("0/1000/250").values().unwrap(),
+      vec!["0", "250", "500", "750", "1000"]
+    );
+    assert_eq!(
+      ExtentValue::parse("0/1/0.3").values().unwrap(),
+      vec!["0", "0.3", "0.6", "0.9"]
+    );
+    assert_eq!(
+      ExtentValue::parse("0/10/0.0"),
+      ExtentValue::Interval {
+        min: "0".to_string(),
+        max: "10".to_string(),
+        resolution: None,
+      }
+    );
+    assert!(ExtentValue::parse("0/1000/-1").values().is_err());
+    assert!(ExtentValue::parse("low/high/1").values().is_err());
+  }
+
+  #[test]
+  fn test_contains() {
+    let time = Dimension {
+      extent: "1999-01-01/2000-08-22/P1D,2005".to_string(),
+      ..Dimension::default()
+    };
+    assert!(time.contains("1999-01-01"));
+    assert!(time.contains("2000-08-22T00:00Z"));
+    assert!(time.contains("2005-01-01"));
+    assert!(time.contains("1999-06-01/2000-01-01,2005"));
+    assert!(!time.contains("2000-08-23"));
+    assert!(!time.contains("1999-06-01/2001-01-01"));
+    assert!(!time.contains("current"));
+    assert!(!time.contains("yesterday"));
+    let time = Dimension {
+      current: true,
+      ..time
+    };
+    assert!(time.contains("current"));
+
+    let elevation = Dimension {
+      extent: "0,1000,3000".to_string(),
+      ..Dimension::default()
+    };
+    assert!(elevation.contains("1000.0"));
+
+    // Values of an interval with a resolution must be on its grid
+    let time = Dimension {
+      extent: "1990/2000/P1Y".to_string(),
+      ..Dimension::default()
+    };
+    assert!(time.contains("1995"));
+    assert!(time.contains("1995-01-01T00:00Z"));
+    assert!(!time.contains("2000-01-01T12:00Z"));
+    let time = Dimension {
+      extent: "2000-08-22T00:00Z/2000-08-23T00:00Z/PT6H,2000-09-01T12:00Z".to_string(),
+      ..Dimension::default()
+    };
+    assert!(time.contains("2000-08-22T08:00+02:00"));
+    assert!(!time.contains("2000-08-22T07:00+02:00"));
+    assert!(time.contains("2000-09-01T14:00+02:00"));
+    let elevation = Dimension {
+      extent: "0/1/0.1".to_string(),
+      ..Dimension::default()
+    };
+    assert!(elevation.contains("0.3"));
+    assert!(!elevation.contains("0.35"));
+    assert!(!elevation.contains("2000"));
+    assert!(Dimension::default().contains("anything"));
+  }
+}
@@ -0,0 +1,232 @@
+//! OGC service exceptions, as reported by WMS 1.1.1 and 1.3.0 servers.
+//!
+//! Both versions share the `ServiceExceptionReport` layout; 1.3.0 adds the `locator`
+//! attribute and the `InvalidCRS`/`InvalidPoint`/`OperationNotSupported` codes.
+use serde_xml_rs::from_reader;
+use std::fmt;
+use xml::reader::{EventReader, XmlEvent};
+
+/// The exception report returned in place of a regular response
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct ServiceExceptionReport {
+  #[serde(default)]
+  pub version: String,
+  #[serde(rename = "ServiceException", default)]
+  pub exceptions: Vec<ServiceException>,
+}
+
+impl ServiceExceptionReport {
+  /// Parse the document as an exception report, if that is what its root element is
+  pub fn from_xml(xml: &str) -> Option<Self> {
+    let root = EventReader::new(xml.as_bytes())
+      .into_iter()
+      .find_map(|event| match event {
+        Ok(XmlEvent::StartElement { name, .. }) => Some(Ok(name.local_name)),
+        Err(e) => Some(Err(e)),
+        _ => None,
+      })?
+      .ok()?;
+    if root == "ServiceExceptionReport" {
+      from_reader(xml.as_bytes()).ok()
+    } else {
+      None
+    }
+  }
+
+  /// The code of the first exception, if any
+  pub fn code(&self) -> Option<&ExceptionCode> {
+    self.exceptions.first().and_then(|e| e.code.as_ref())
+  }
+
+  /// Whether any of the exceptions carries the given code
+  pub fn has_code(&self, code: &ExceptionCode) -> bool {
+    self
+      .exceptions
+      .iter()
+      .any(|e| e.code.as_ref() == Some(code))
+  }
+}
+
+impl fmt::Display for ServiceExceptionReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let messages: Vec<String> = self.exceptions.iter().map(|e| e.to_string()).collect();
+    write!(f, "Service exception: {}", messages.join("; "))
+  }
+}
+
+impl std::error::Error for ServiceExceptionReport {}
+
+/// A single exception within a report
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct ServiceException {
+  /// The exception code, absent for errors the server did not classify
+  #[serde(default)]
+  pub code: Option<ExceptionCode>,
+  /// The parameter or element the exception refers to (1.3.0 only)
+  #[serde(default)]
+  pub locator: Option<String>,
+  /// The human readable description
+  #[serde(rename = "$value", default)]
+  pub message: String,
+}
+
+impl fmt::Display for ServiceException {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if let Some(code) = &self.code {
+      write!(f, "[{}] ", code.as_str())?;
+    }
+    if let Some(locator) = &self.locator {
+      write!(f, "({}) ", locator)?;
+    }
+    write!(f, "{}", self.message.trim())
+  }
+}
+
+/// The exception codes defined by the WMS specifications
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(from = "String", into = "String")]
+pub enum ExceptionCode {
+  /// Request contains a Format not offered by the server
+  InvalidFormat,
+  /// Request contains a CRS (InvalidSRS in 1.1.x) not offered by the server for one or more
+  /// of the Layers in the request
+  InvalidCRS,
+  /// GetMap request is for a Layer not offered by the server, or GetFeatureInfo request is for
+  /// a Layer not shown on the map
+  LayerNotDefined,
+  /// Request is for a Layer in a Style not offered by the server
+  StyleNotDefined,
+  /// GetFeatureInfo request is applied to a Layer which is not declared queryable
+  LayerNotQueryable,
+  /// GetFeatureInfo request contains invalid I or J value
+  InvalidPoint,
+  /// Value of (optional) UpdateSequence parameter is equal to the current value
+  CurrentUpdateSequence,
+  /// Value of (optional) UpdateSequence parameter is greater than the current value
+  InvalidUpdateSequence,
+  /// Request does not include a sample dimension value, and the server did not declare a
+  /// default value for that dimension
+  MissingDimensionValue,
+  /// Request contains an invalid sample dimension value
+  InvalidDimensionValue,
+  /// Request is for an optional operation that is not supported by the server
+  OperationNotSupported,
+  /// A code outside of the specification, e.g. a vendor specific one
+  Other(String),
+}
+
+impl ExceptionCode {
+  /// The code as it appears in the exception report (1.3.0 spelling)
+  pub fn as_str(&self) -> &str {
+    match self {
+      ExceptionCode::InvalidFormat => "InvalidFormat",
+      ExceptionCode::InvalidCRS => "InvalidCRS",
+      ExceptionCode::LayerNotDefined => "LayerNotDefined",
+      ExceptionCode::StyleNotDefined => "StyleNotDefined",
+      ExceptionCode::LayerNotQueryable => "LayerNotQueryable",
+      ExceptionCode::InvalidPoint => "InvalidPoint",
+      ExceptionCode::CurrentUpdateSequence => "CurrentUpdateSequence",
+      ExceptionCode::InvalidUpdateSequence => "InvalidUpdateSequence",
+      ExceptionCode::MissingDimensionValue => "MissingDimensionValue",
+      ExceptionCode::InvalidDimensionValue => "InvalidDimensionValue",
+      ExceptionCode::OperationNotSupported => "OperationNotSupported",
+      ExceptionCode::Other(code) => code,
+    }
+  }
+}
+
+impl From<&str> for ExceptionCode {
+  fn from(code: &str) -> Self {
+    match code.trim() {
+      "InvalidFormat" => ExceptionCode::InvalidFormat,
+      "InvalidCRS" | "InvalidSRS" => ExceptionCode::InvalidCRS,
+      "LayerNotDefined" => ExceptionCode::LayerNotDefined,
+      "StyleNotDefined" => ExceptionCode::StyleNotDefined,
+      "LayerNotQueryable" => ExceptionCode::LayerNotQueryable,
+      "InvalidPoint" => ExceptionCode::InvalidPoint,
+      "CurrentUpdateSequence" => ExceptionCode::CurrentUpdateSequence,
+      "InvalidUpdateSequence" => ExceptionCode::InvalidUpdateSequence,
+      "MissingDimensionValue" => ExceptionCode::MissingDimensionValue,
+      "InvalidDimensionValue" => ExceptionCode::InvalidDimensionValue,
+      "OperationNotSupported" => ExceptionCode::OperationNotSupported,
+      other => ExceptionCode::Other(other.to_string()),
+    }
+  }
+}
+
+impl From<String> for ExceptionCode {
+  fn from(code: String) -> Self {
+    ExceptionCode::from(code.as_str())
+  }
+}
+
+impl From<ExceptionCode> for String {
+  fn from(code: ExceptionCode) -> Self {
+    code.as_str().to_string()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{ExceptionCode, ServiceException, ServiceExceptionReport};
+
+  #[test]
+  fn test_parse_v1_1_1() {
+    let xml = r#"<?xml version='1.0' encoding="UTF-8"?>
+      <!DOCTYPE ServiceExceptionReport SYSTEM "http://schemas.opengis.net/wms/1.1.1/exception_1_1_1.dtd">
+      <ServiceExceptionReport version="1.1.1">
+        <ServiceException code="InvalidSRS">
+          Error occurred decoding the espg code EPSG:1
+        </ServiceException>
+      </ServiceExceptionReport>"#;
+    let report = ServiceExceptionReport::from_xml(xml).unwrap();
+    assert_eq!(report.version, "1.1.1");
+    assert_eq!(
+      report.exceptions,
+      vec![ServiceException {
+        code: Some(ExceptionCode::InvalidCRS),
+        locator: None,
+        message: "Error occurred decoding the espg code EPSG:1".to_string(),
+      }]
+    );
+    assert_eq!(report.code(), Some(&ExceptionCode::InvalidCRS));
+  }
+
+  #[test]
+  fn test_parse_v1_3_0() {
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+      <ServiceExceptionReport version="1.3.0" xmlns="http://www.opengis.net/ogc"
+        xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"
+        xsi:schemaLocation="http://www.opengis.net/ogc http://schemas.opengis.net/wms/1.3.0/exceptions_1_3_0.xsd">
+        <ServiceException code="LayerNotDefined" locator="LAYERS">Could not find layer foo</ServiceException>
+        <ServiceException code="MissingParameterValue"><![CDATA[Missing BBOX]]></ServiceException>
+        <ServiceException>Something else</ServiceException>
+      </ServiceExceptionReport>"#;
+    let report = ServiceExceptionReport::from_xml(xml).unwrap();
+    assert_eq!(report.exceptions.len(), 3);
+    assert_eq!(report.code(), Some(&ExceptionCode::LayerNotDefined));
+    assert_eq!(report.exceptions[0].locator, Some("LAYERS".to_string()));
+    assert_eq!(
+      report.exceptions[1].code,
+      Some(ExceptionCode::Other("MissingParameterValue".to_string()))
+    );
+    assert_eq!(report.exceptions[1].message, "Missing BBOX");
+    assert_eq!(report.exceptions[2].code, None);
+    assert!(report.has_code(&ExceptionCode::LayerNotDefined));
+    assert!(!report.has_code(&ExceptionCode::InvalidFormat));
+    assert_eq!(
+      report.to_string(),
+      "Service exception: [LayerNotDefined] (LAYERS) Could not find layer foo; \
+       [MissingParameterValue] Missing BBOX; Something else"
+    );
+  }
+
+  #[test]
+  fn test_not_a_report() {
+    assert_eq!(
+      ServiceExceptionReport::from_xml("<WMS_Capabilities version=\"1.3.0\"/>"),
+      None
+    );
+    assert_eq!(ServiceExceptionReport::from_xml("not xml"), None);
+  }
+}
@@ -0,0 +1,427 @@
+//! Decoding of GetFeatureInfo response bodies into features.
+//!
+//! Servers are free to choose the layout of their `text/plain` and `text/html`
+//! responses, so those decoders follow the conventions used by GeoServer and
+//! MapServer, which cover the majority of deployments.
+use crate::error::Error;
+use std::collections::BTreeMap;
+use xml::reader::{EventReader, XmlEvent};
+
+/// A single feature returned by a GetFeatureInfo request
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Feature {
+  /// The layer (or feature type) the feature belongs to, when the format reports it
+  pub layer: Option<String>,
+  /// The feature identifier, when the format reports it
+  pub id: Option<String>,
+  /// The feature's attributes as name/value pairs
+  pub properties: BTreeMap<String, String>,
+}
+
+impl Feature {
+  fn new(layer: Option<String>, id: Option<String>) -> Self {
+    Feature {
+      layer,
+      id,
+      properties: BTreeMap::new(),
+    }
+  }
+}
+
+/// Decode the response body according to its MIME type
+pub(crate) fn decode(format: &str, body: &str) -> crate::Result<Vec<Feature>> {
+  let mime = format
+    .split(';')
+    .next()
+    .unwrap_or_default()
+    .trim()
+    .to_lowercase();
+  match mime.as_str() {
+    "text/plain" => Ok(decode_plain(body)),
+    "text/html" => Ok(decode_html(body)),
+    "application/json" | "application/geo+json" => decode_json(body),
+    "application/vnd.ogc.gml" | "application/vnd.ogc.gml/3.1.1" | "text/xml" => decode_gml(body),
+    _ => Err(Error::UnsupportedContentType(format.to_string())),
+  }
+}
+
+/// Decode `key = value` lines, grouped into features by the GeoServer `----` separators or
+/// the MapServer `Feature <id>:` headings.
+fn decode_plain(body: &str) -> Vec<Feature> {
+  let mut features = Vec::new();
+  let mut layer = None;
+  let mut current: Option<Feature> = None;
+  for line in body.lines().map(str::trim) {
+    if line.starts_with("Results for FeatureType") || line.starts_with("Layer '") {
+      features.extend(current.take());
+      layer = quoted(line);
+    } else if line.starts_with("Feature ") && line.ends_with(':') {
+      features.extend(current.take());
+      let id = line["Feature ".len()..line.len() - 1].trim().to_string();
+      current = Some(Feature::new(layer.clone(), Some(id)));
+    } else if line.starts_with("---") {
+      features.extend(current.take());
+    } else if let Some((key, value)) = line.split_once('=') {
+      current
+        .get_or_insert_with(|| Feature::new(layer.clone(), None))
+        .properties
+        .insert(
+          key.trim().to_string(),
+          value.trim().trim_matches('\'').to_string(),
+        );
+    }
+  }
+  features.extend(current);
+  features.retain(|f| !f.properties.is_empty());
+  features
+}
+
+/// Decode HTML tables, where each table holds the features of one layer: the caption names
+/// the layer, the `th` row names the attributes and each `td` row is a feature.
+fn decode_html(body: &str) -> Vec<Feature> {
+  let mut features = Vec::new();
+  for table in elements(body, "table") {
+    let layer = elements(table, "caption").next().map(strip_tags);
+    let mut header = Vec::new();
+    for row in elements(table, "tr") {
+      let headings: Vec<String> = elements(row, "th").map(strip_tags).collect();
+      if !headings.is_empty() {
+        header = headings;
+        continue;
+      }
+      let mut feature = Feature::new(layer.clone(), None);
+      for (name, value) in header.iter().zip(elements(row, "td").map(strip_tags)) {
+        if name.eq_ignore_ascii_case("fid") {
+          feature.id = Some(value);
+        } else {
+          feature.properties.insert(name.clone(), value);
+        }
+      }
+      if !feature.properties.is_empty() {
+        features.push(feature);
+      }
+    }
+  }
+  features
+}
+
+/// Decode a GeoJSON feature collection
+fn decode_json(body: &str) -> crate::Result<Vec<Feature>> {
+  let json: serde_json::Value = serde_json::from_str(body)?;
+  let features = json
+    .get("features")
+    .and_then(|f| f.as_array())
+    .map(|features| {
+      features
+        .iter()
+        .map(|f| {
+          let id = f.get("id").map(json_to_string);
+          let mut feature = Feature::new(None, id);
+          if let Some(props) = f.get("properties").and_then(|p| p.as_object()) {
+            for (name, value) in props {
+              feature
+                .properties
+                .insert(name.clone(), json_to_string(value));
+            }
+          }
+          feature
+        })
+        .collect()
+    })
+    .unwrap_or_default();
+  Ok(features)
+}
+
+/// Decode a GML feature collection (`gml:featureMember`) or MapServer's `msGMLOutput`,
+/// keeping the simple (text only) properties of each feature.
+fn decode_gml(body: &str) -> crate::Result<Vec<Feature>> {
+  let mut features = Vec::new();
+  let mut path: Vec<String> = Vec::new();
+  // The feature being read and the depth of its element
+  let mut current: Option<(Feature, usize)> = None;
+  // The property being read, its value and whether it has child elements
+  let mut property: Option<(String, String, bool)> = None;
+
+  for event in EventReader::new(body.as_bytes()) {
+    match event? {
+      XmlEvent::StartElement {
+        name, attributes, ..
+      } => {
+        let parent = path.last().map(String::as_str).unwrap_or_default();
+        match &current {
+          None if parent.starts_with("featureMember") || name.local_name.ends_with("_feature") => {
+            let layer = name
+              .local_name
+              .strip_suffix("_feature")
+              .unwrap_or(&name.local_name)
+              .to_string();
+            let id = attributes
+              .iter()
+              .find(|a| a.name.local_name == "fid" || a.name.local_name == "id")
+              .map(|a| a.value.clone());
+            current = Some((Feature::new(Some(layer), id), path.len() + 1));
+          }
+          Some((_, depth)) if path.len() == *depth => {
+            property = Some((name.local_name.clone(), String::new(), false));
+          }
+          Some(_) => {
+            if let Some(p) = property.as_mut() {
+              p.2 = true;
+            }
+          }
+          None => {}
+        }
+        path.push(name.local_name);
+      }
+      XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+        if let (Some((_, depth)), Some(p)) = (&current, property.as_mut()) {
+          if path.len() == depth + 1 {
+            p.1.push_str(&text);
+          }
+        }
+      }
+      XmlEvent::EndElement { .. } => {
+        let depth = path.len();
+        path.pop();
+        match current.take() {
+          Some((feature, d)) if d == depth => features.push(feature),
+          Some((mut feature, d)) => {
+            if d + 1 == depth {
+              if let Some((name, value, false)) = property.take() {
+                if name != "boundedBy" {
+                  feature.properties.insert(name, value.trim().to_string());
+                }
+              }
+            }
+            current = Some((feature, d));
+          }
+          None => {}
+        }
+      }
+      _ => {}
+    }
+  }
+  Ok(features)
+}
+
+/// The text between the first pair of single quotes
+fn quoted(line: &str) -> Option<String> {
+  let start = line.find('\'')? + 1;
+  let len = line[start..].find('\'')?;
+  Some(line[start..start + len].to_string())
+}
+
+/// Iterate over the inner HTML of each (non-nested) `tag` element
+fn elements<'a>(html: &'a str, tag: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+  let lower = html.to_ascii_lowercase();
+  let open = format!("<{}", tag);
+  let close = format!("</{}>", tag);
+  let mut offset = 0;
+  std::iter::from_fn(move || loop {
+    let start = offset + lower[offset..].find(&open)?;
+    let after_name = start + open.len();
+    // Skip longer tag names sharing the prefix, e.g. <th> vs <thead>
+    if !matches!(
+      lower[after_name..].chars().next(),
+      Some('>') | Some(' ') | Some('/') | Some('\t') | Some('\n') | Some('\r')
+    ) {
+      offset = after_name;
+      continue;
+    }
+    let content = after_name + lower[after_name..].find('>')? + 1;
+    let end = lower[content..]
+      .find(&close)
+      .map(|e| content + e)
+      .unwrap_or_else(|| lower.len());
+    offset = end;
+    return Some(&html[content..end]);
+  })
+}
+
+/// Remove markup and decode the common entities
+fn strip_tags(html: &str) -> String {
+  let mut text = String::new();
+  let mut in_tag = false;
+  for c in html.chars() {
+    match c {
+      '<' => in_tag = true,
+      '>' => in_tag = false,
+      c if !in_tag => text.push(c),
+      _ => {}
+    }
+  }
+  text
+    .replace("&nbsp;", " ")
+    .replace("&lt;", "<")
+    .replace("&gt;", ">")
+    .replace("&quot;", "\"")
+    .replace("&#39;", "'")
+    .replace("&amp;", "&")
+    .trim()
+    .to_string()
+}
+
+fn json_to_string(value: &serde_json::Value) -> String {
+  match value {
+    serde_json::Value::String(s) => s.clone(),
+    serde_json::Value::Null => String::new(),
+    v => v.to_string(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{decode, Feature};
+
+  fn feature(layer: Option<&str>, id: Option<&str>, props: &[(&str, &str)]) -> Feature {
+    Feature {
+      layer: layer.map(String::from),
+      id: id.map(String::from),
+      properties: props
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect(),
+    }
+  }
+
+  #[test]
+  fn test_decode_plain_geoserver() {
+    let body = "Results for FeatureType 'topp:states':\n\
+                --------------------------------------------\n\
+                STATE_NAME = Illinois\n\
+                STATE_ABBR = IL\n\
+                --------------------------------------------\n\
+                STATE_NAME = Indiana\n\
+                STATE_ABBR = IN\n\
+                --------------------------------------------\n";
+    assert_eq!(
+      decode("text/plain", body).unwrap(),
+      vec![
+        feature(
+          Some("topp:states"),
+          None,
+          &[("STATE_ABBR", "IL"), ("STATE_NAME", "Illinois")]
+        ),
+        feature(
+          Some("topp:states"),
+          None,
+          &[("STATE_ABBR", "IN"), ("STATE_NAME", "Indiana")]
+        ),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_decode_plain_mapserver() {
+    let body = "GetFeatureInfo results:\n\n\
+                Layer 'cities'\n\
+                \x20 Feature 12: \n\
+                \x20   NAME = 'Boston'\n\
+                \x20   POP = '675647'\n";
+    assert_eq!(
+      decode("text/plain; charset=UTF-8", body).unwrap(),
+      vec![feature(
+        Some("cities"),
+        Some("12"),
+        &[("NAME", "Boston"), ("POP", "675647")]
+      )]
+    );
+  }
+
+  #[test]
+  fn test_decode_html() {
+    let body = r#"<html><body>
+      <table class="featureInfo">
+        <caption class="featureInfo">states</caption>
+        <tr><th>fid</th><th>STATE_NAME</th><th>PERSONS</th></tr>
+        <tr><td>states.13</td><td>Illinois</td><td>11430602.0</td></tr>
+        <tr class="odd"><td>states.14</td><td>New &amp; Improved</td><td>5544159.0</td></tr>
+      </table>
+    </body></html>"#;
+    assert_eq!(
+      decode("text/html", body).unwrap(),
+      vec![
+        feature(
+          Some("states"),
+          Some("states.13"),
+          &[("PERSONS", "11430602.0"), ("STATE_NAME", "Illinois")]
+        ),
+        feature(
+          Some("states"),
+          Some("states.14"),
+          &[("PERSONS", "5544159.0"), ("STATE_NAME", "New & Improved")]
+        ),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_decode_json() {
+    let body = r#"{"type":"FeatureCollection","features":[
+      {"type":"Feature","id":"states.13","geometry":null,
+       "properties":{"STATE_NAME":"Illinois","PERSONS":11430602,"NOTE":null}}]}"#;
+    assert_eq!(
+      decode("application/json", body).unwrap(),
+      vec![feature(
+        None,
+        Some("states.13"),
+        &[
+          ("NOTE", ""),
+          ("PERSONS", "11430602"),
+          ("STATE_NAME", "Illinois")
+        ]
+      )]
+    );
+  }
+
+  #[test]
+  fn test_decode_gml_feature_collection() {
+    let body = r#"<?xml version="1.0" encoding="UTF-8"?>
+      <wfs:FeatureCollection xmlns:wfs="http://www.opengis.net/wfs"
+        xmlns:gml="http://www.opengis.net/gml" xmlns:topp="http://www.openplans.org/topp">
+        <gml:featureMember>
+          <topp:states fid="states.13">
+            <gml:boundedBy><gml:Box><gml:coordinates>1,2 3,4</gml:coordinates></gml:Box></gml:boundedBy>
+            <topp:the_geom><gml:MultiPolygon/></topp:the_geom>
+            <topp:STATE_NAME>Illinois</topp:STATE_NAME>
+            <topp:STATE_ABBR>IL</topp:STATE_ABBR>
+          </topp:states>
+        </gml:featureMember>
+      </wfs:FeatureCollection>"#;
+    assert_eq!(
+      decode("application/vnd.ogc.gml", body).unwrap(),
+      vec![feature(
+        Some("states"),
+        Some("states.13"),
+        &[("STATE_ABBR", "IL"), ("STATE_NAME", "Illinois")]
+      )]
+    );
+  }
+
+  #[test]
+  fn test_decode_gml_mapserver() {
+    let body = r#"<?xml version="1.0" encoding="ISO-8859-1"?>
+      <msGMLOutput xmlns:gml="http://www.opengis.net/gml">
+        <cities_layer>
+          <gml:name>cities</gml:name>
+          <cities_feature>
+            <NAME>Boston</NAME>
+            <POP>675647</POP>
+          </cities_feature>
+        </cities_layer>
+      </msGMLOutput>"#;
+    assert_eq!(
+      decode("application/vnd.ogc.gml", body).unwrap(),
+      vec![feature(
+        Some("cities"),
+        None,
+        &[("NAME", "Boston"), ("POP", "675647")]
+      )]
+    );
+  }
+
+  #[test]
+  fn test_decode_unsupported() {
+    assert!(decode("image/png", "").is_err());
+  }
+}
@@ -0,0 +1,512 @@
+//! Just enough ISO 8601 to enumerate WMS time extents (Annex D of the WMS 1.3.0
+//! specification): instants from year down to millisecond precision, in UTC or with a time
+//! zone offset, and periods such as `P1D` or `PT30M`.
+use crate::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MILLIS_PER_DAY: i64 = 86_400_000;
+
+/// The largest year, and the longest period in years, that is accepted. This keeps the
+/// arithmetic on the milliseconds of extents given by a server far from overflowing.
+const MAX_YEARS: i64 = 999_999;
+
+/// The precision an instant is written with, coarsest first
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Precision {
+  Year,
+  Month,
+  Day,
+  Hour,
+  Minute,
+  Second,
+  Millisecond,
+}
+
+/// A point in time, as written in a time extent, e.g. `2000-08-22` or `2000-08-22T12:30Z`
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Instant {
+  year: i64,
+  month: u32,
+  day: u32,
+  /// Milliseconds since midnight
+  millis: i64,
+  precision: Precision,
+  /// Whether the time of day is followed by the `Z` designator
+  zulu: bool,
+}
+
+impl Instant {
+  fn parse(s: &str) -> Option<Instant> {
+    let s = s.trim();
+    if s.eq_ignore_ascii_case("present") || s.eq_ignore_ascii_case("current") {
+      return Some(Instant::now());
+    }
+    let (s, zulu) = match s.strip_suffix('Z') {
+      Some(s) => (s, true),
+      None => (s, false),
+    };
+    let mut parts = s.splitn(2, 'T');
+    let date: Vec<&str> = parts.next()?.split('-').collect();
+    let (time, offset) = match parts.next() {
+      Some(time) if !zulu => match time.find(['+', '-']) {
+        Some(i) => (Some(&time[..i]), Some(parse_offset(&time[i..])?)),
+        None => (Some(time), None),
+      },
+      time => (time, None),
+    };
+    let year: i64 = date[0].parse().ok()?;
+    if year > MAX_YEARS {
+      return None;
+    }
+    let month = date.get(1).map_or(Some(1), |m| m.parse().ok())?;
+    let day = date.get(2).map_or(Some(1), |d| d.parse().ok())?;
+    if date.len() > 3 || !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+      return None;
+    }
+    let mut precision = [Precision::Year, Precision::Month, Precision::Day][date.len() - 1];
+    let mut millis = 0;
+    if let Some(time) = time {
+      if precision != Precision::Day {
+        return None;
+      }
+      let fields: Vec<&str> = time.split(':').collect();
+      let hour: i64 = fields[0].parse().ok()?;
+      let minute: i64 = fields.get(1).map_or(Some(0), |m| m.parse().ok())?;
+      let second: f64 = fields.get(2).map_or(Some(0.0), |s| s.parse().ok())?;
+      if fields.len() > 3 || hour > 23 || minute > 59 || !(0.0..60.0).contains(&second) {
+        return None;
+      }
+      precision = match fields.len() {
+        1 => Precision::Hour,
+        2 => Precision::Minute,
+        _ if fields[2].contains('.') => Precision::Millisecond,
+        _ => Precision::Second,
+      };
+      millis = (hour * 60 + minute) * 60_000 + (second * 1000.0).round() as i64;
+    }
+    if let Some(offset) = offset {
+      // Moved to UTC, so that instants compare by their epoch milliseconds
+      let local = days_from_civil(year, month, day) * MILLIS_PER_DAY + millis;
+      return Some(Instant {
+        precision,
+        ..Instant::from_epoch_millis(local - offset)
+      });
+    }
+    Some(Instant {
+      year,
+      month,
+      day,
+      millis,
+      precision,
+      zulu,
+    })
+  }
+
+  /// The current time, to the second
+  fn now() -> Instant {
+    let elapsed = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map_or(0, |d| d.as_secs() as i64 * 1000);
+    let mut now = Instant::from_epoch_millis(elapsed);
+    now.precision = Precision::Second;
+    now.zulu = true;
+    now
+  }
+
+  fn from_epoch_millis(epoch_millis: i64) -> Instant {
+    let (year, month, day) = civil_from_days(epoch_millis.div_euclid(MILLIS_PER_DAY));
+    Instant {
+      year,
+      month,
+      day,
+      millis: epoch_millis.rem_euclid(MILLIS_PER_DAY),
+      precision: Precision::Millisecond,
+      zulu: true,
+    }
+  }
+
+  fn epoch_millis(&self) -> i64 {
+    days_from_civil(self.year, self.month, self.day) * MILLIS_PER_DAY + self.millis
+  }
+
+  /// This instant moved on by `times` periods. Years and months are added first, clamping
+  /// the day to the end of the month, then the remainder of the period. `None` if this
+  /// gives a year beyond `MAX_YEARS`.
+  fn add(&self, period: &Period, times: i64) -> Option<Instant> {
+    let months = period
+      .months
+      .checked_mul(times)?
+      .checked_add(self.year * 12 + i64::from(self.month) - 1)?;
+    let (year, month) = (months.div_euclid(12), months.rem_euclid(12) as u32 + 1);
+    if year.abs() > MAX_YEARS {
+      return None;
+    }
+    let day = self.day.min(days_in_month(year, month));
+    let start = days_from_civil(year, month, day) * MILLIS_PER_DAY + self.millis;
+    let instant = Instant::from_epoch_millis(period.millis.checked_mul(times)?.checked_add(start)?);
+    if instant.year.abs() > MAX_YEARS {
+      return None;
+    }
+    Some(Instant {
+      precision: self.precision,
+      zulu: self.zulu,
+      ..instant
+    })
+  }
+
+  fn format(&self, precision: Precision) -> String {
+    let zulu = if self.zulu { "Z" } else { "" };
+    let (hour, minute) = (self.millis / 3_600_000, self.millis / 60_000 % 60);
+    let (second, millis) = (self.millis / 1000 % 60, self.millis % 1000);
+    match precision {
+      Precision::Year => format!("{:04}", self.year),
+      Precision::Month => format!("{:04}-{:02}", self.year, self.month),
+      Precision::Day => format!("{:04}-{:02}-{:02}", self.year, self.month, self.day),
+      Precision::Hour => format!("{}T{:02}{}", self.format(Precision::Day), hour, zulu),
+      Precision::Minute => format!(
+        "{}T{:02}:{:02}{}",
+        self.format(Precision::Day),
+        hour,
+        minute,
+        zulu
+      ),
+      Precision::Second => format!(
+        "{}T{:02}:{:02}:{:02}{}",
+        self.format(Precision::Day),
+        hour,
+        minute,
+        second,
+        zulu
+      ),
+      Precision::Millisecond => format!(
+        "{}T{:02}:{:02}:{:02}.{:03}{}",
+        self.format(Precision::Day),
+        hour,
+        minute,
+        second,
+        millis,
+        zulu
+      ),
+    }
+  }
+}
+
+/// A duration such as `P1Y2M`, `P1W` or `PT1.5S`, split into its calendar part (months) and
+/// its exact part (milliseconds)
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Period {
+  months: i64,
+  millis: i64,
+  /// The precision of the smallest component
+  precision: Precision,
+}
+
+impl Period {
+  fn parse(s: &str) -> Option<Period> {
+    let s = s.trim().strip_prefix('P')?;
+    let mut period = Period {
+      months: 0,
+      millis: 0,
+      precision: Precision::Year,
+    };
+    // Summed as floats, which cannot overflow, and checked against `MAX_YEARS`
+    let (mut months, mut millis) = (0.0, 0.0);
+    let mut in_time = false;
+    let mut number = String::new();
+    for c in s.chars() {
+      if c.is_ascii_digit() || c == '.' {
+        number.push(c);
+        continue;
+      }
+      if c == 'T' && !in_time && number.is_empty() {
+        in_time = true;
+        continue;
+      }
+      let n: f64 = number.parse().ok()?;
+      number.clear();
+      if n.fract() != 0.0 && !(in_time && c == 'S') {
+        return None;
+      }
+      let (add_months, add_millis, precision) = match (in_time, c) {
+        (false, 'Y') => (12.0 * n, 0.0, Precision::Year),
+        (false, 'M') => (n, 0.0, Precision::Month),
+        (false, 'W') => (0.0, 7.0 * n * MILLIS_PER_DAY as f64, Precision::Day),
+        (false, 'D') => (0.0, n * MILLIS_PER_DAY as f64, Precision::Day),
+        (true, 'H') => (0.0, n * 3_600_000.0, Precision::Hour),
+        (true, 'M') => (0.0, n * 60_000.0, Precision::Minute),
+        (true, 'S') if n.fract() != 0.0 => (0.0, n * 1000.0, Precision::Millisecond),
+        (true, 'S') => (0.0, n * 1000.0, Precision::Second),
+        _ => return None,
+      };
+      months += add_months;
+      millis += add_millis;
+      period.precision = period.precision.max(precision);
+    }
+    let max_millis = MAX_YEARS * 366 * MILLIS_PER_DAY;
+    if !number.is_empty() || months > (12 * MAX_YEARS) as f64 || millis > max_millis as f64 {
+      return None;
+    }
+    period.months = months as i64;
+    period.millis = millis.round() as i64;
+    if period.months > 0 || period.millis > 0 {
+      Some(period)
+    } else {
+      None
+    }
+  }
+}
+
+/// Every instant from `start` to `end` inclusive, `period` apart, written with the precision
+/// of the start or of the period, whichever is finer. More than `limit` instants are an error.
+pub(crate) fn enumerate(
+  start: &str,
+  end: &str,
+  period: &str,
+  limit: usize,
+) -> crate::Result<Vec<String>> {
+  let invalid = |what: &str, value: &str| {
+    Error::InvalidParameter(format!("Invalid ISO 8601 {}: {:?}", what, value))
+  };
+  let mut first = Instant::parse(start).ok_or_else(|| invalid("instant", start))?;
+  let last = Instant::parse(end).ok_or_else(|| invalid("instant", end))?;
+  let step = Period::parse(period).ok_or_else(|| invalid("period", period))?;
+  let precision = first.precision.max(step.precision);
+  if first.precision <= Precision::Day {
+    // Times of day added by the period are given in UTC
+    first.zulu = true;
+  }
+  let last = last.epoch_millis();
+  let mut values = Vec::new();
+  for times in 0.. {
+    // Past the largest year is past the end too
+    let instant = match first.add(&step, times) {
+      Some(instant) if instant.epoch_millis() <= last => instant,
+      _ => break,
+    };
+    if values.len() == limit {
+      return Err(Error::InvalidParameter(format!(
+        "The interval {}/{}/{} has more than {} values",
+        start, end, period, limit
+      )));
+    }
+    values.push(instant.format(precision));
+  }
+  Ok(values)
+}
+
+/// The milliseconds of a time zone offset such as `+02:00`, `-0530` or `+01`
+fn parse_offset(s: &str) -> Option<i64> {
+  let sign = if s.starts_with('-') { -1 } else { 1 };
+  let digits: String = s[1..].chars().filter(|c| *c != ':').collect();
+  if !matches!(digits.len(), 2 | 4) || !digits.chars().all(|c| c.is_ascii_digit()) {
+    return None;
+  }
+  let hours: i64 = digits[..2].parse().ok()?;
+  let minutes: i64 = digits
+    .get(2..)
+    .filter(|m| !m.is_empty())
+    .map_or(Some(0), |m| m.parse().ok())?;
+  if hours > 23 || minutes > 59 {
+    return None;
+  }
+  Some(sign * (hours * 60 + minutes) * 60_000)
+}
+
+/// The instant as milliseconds since 1970-01-01T00:00Z, for comparisons
+pub(crate) fn epoch_millis(instant: &str) -> Option<i64> {
+  Instant::parse(instant).map(|i| i.epoch_millis())
+}
+
+/// Whether `instant` is `start` plus a whole number of periods, or `None` if any of them
+/// cannot be parsed
+pub(crate) fn is_on_grid(start: &str, instant: &str, period: &str) -> Option<bool> {
+  let first = Instant::parse(start)?;
+  let instant = Instant::parse(instant)?.epoch_millis();
+  let step = Period::parse(period)?;
+  let offset = instant - first.epoch_millis();
+  if step.months == 0 {
+    return Some(offset.rem_euclid(step.millis) == 0);
+  }
+  // The months vary in length, so try the multiples around the average length
+  let average = step
+    .months
+    .checked_mul(MILLIS_PER_DAY * 146_097 / 4800)?
+    .checked_add(step.millis)?;
+  let times = offset.div_euclid(average);
+  Some(
+    ((times - 1)..=(times + 1))
+      .any(|t| t >= 0 && first.add(&step, t).map(|i| i.epoch_millis()) == Some(instant)),
+  )
+}
+
+fn is_leap_year(year: i64) -> bool {
+  year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+  match month {
+    2 if is_leap_year(year) => 29,
+    2 => 28,
+    4 | 6 | 9 | 11 => 30,
+    _ => 31,
+  }
+}
+
+/// Days since 1970-01-01 of the given proleptic Gregorian date, of a year within `MAX_YEARS`
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = year.div_euclid(400);
+  let year_of_era = year - era * 400;
+  let month = i64::from(month);
+  let day_of_year =
+    (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+  let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+  era * 146_097 + day_of_era - 719_468
+}
+
+/// The proleptic Gregorian date of the given number of days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+  let days = days + 719_468;
+  let era = days.div_euclid(146_097);
+  let day_of_era = days - era * 146_097;
+  let year_of_era =
+    (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let mp = (5 * day_of_year + 2) / 153;
+  let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+  let year = year_of_era + era * 400;
+  (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    civil_from_days, days_from_civil, enumerate, is_on_grid, Instant, Period, Precision,
+  };
+
+  #[test]
+  fn test_parse_instant() {
+    let instant = Instant::parse("2000-08-22T12:30:15.250Z").unwrap();
+    assert_eq!((instant.year, instant.month, instant.day), (2000, 8, 22));
+    assert_eq!(instant.millis, 45_015_250);
+    assert_eq!(instant.precision, Precision::Millisecond);
+    assert_eq!(
+      instant.format(Precision::Millisecond),
+      "2000-08-22T12:30:15.250Z"
+    );
+    assert_eq!(Instant::parse("1992").unwrap().precision, Precision::Year);
+    assert_eq!(
+      Instant::parse("1992-02").unwrap().format(Precision::Day),
+      "1992-02-01"
+    );
+    assert!(Instant::parse("1999-02-29").is_none());
+    assert!(Instant::parse("2000-13").is_none());
+    assert!(Instant::parse("2000T12").is_none());
+    assert!(Instant::parse("yesterday").is_none());
+
+    let offset = Instant::parse("2000-08-22T01:30+02:00").unwrap();
+    assert_eq!(offset.format(Precision::Minute), "2000-08-21T23:30Z");
+    assert_eq!(
+      Instant::parse("2000-08-22T12-0530").unwrap().epoch_millis(),
+      Instant::parse("2000-08-22T17:30Z").unwrap().epoch_millis()
+    );
+    assert!(Instant::parse("2000-08-22T12+2").is_none());
+    assert_eq!(Instant::parse("999999").unwrap().year, 999_999);
+    assert!(Instant::parse("99999999999").is_none());
+    assert!(Instant::parse("99999999999999999999").is_none());
+  }
+
+  #[test]
+  fn test_parse_period() {
+    let period = Period::parse("P1Y2M3DT4H5M6S").unwrap();
+    assert_eq!(period.months, 14);
+    assert_eq!(period.millis, ((3 * 24 + 4) * 60 + 5) * 60_000 + 6000);
+    assert_eq!(period.precision, Precision::Second);
+    assert_eq!(Period::parse("P2W").unwrap().millis, 14 * 86_400_000);
+    assert_eq!(
+      Period::parse("PT0.5S").unwrap().precision,
+      Precision::Millisecond
+    );
+    assert!(Period::parse("P").is_none());
+    assert!(Period::parse("P0D").is_none());
+    assert!(Period::parse("P1H").is_none());
+    assert!(Period::parse("1D").is_none());
+    assert_eq!(Period::parse("P999999Y").unwrap().months, 12 * 999_999);
+    assert!(Period::parse("P9999999999Y").is_none());
+    assert!(Period::parse("P999999Y999999M").is_none());
+    assert!(Period::parse("PT99999999999999999999S").is_none());
+  }
+
+  #[test]
+  fn test_civil_days() {
+    assert_eq!(days_from_civil(1970, 1, 1), 0);
+    assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+    for days in -800_000..800_000 {
+      let (y, m, d) = civil_from_days(days);
+      assert_eq!(days_from_civil(y, m, d), days);
+    }
+  }
+
+  #[test]
+  fn test_is_on_grid() {
+    assert_eq!(is_on_grid("1990", "1995", "P1Y"), Some(true));
+    assert_eq!(is_on_grid("1990", "1995-01-01T00:00Z", "P1Y"), Some(true));
+    assert_eq!(is_on_grid("1990", "2000-01-01T12:00Z", "P1Y"), Some(false));
+    assert_eq!(is_on_grid("2000-01-31", "2000-04-30", "P1M"), Some(true));
+    assert_eq!(is_on_grid("2000-01-31", "2000-04-29", "P1M"), Some(false));
+    assert_eq!(
+      is_on_grid("2000-08-22T00:00Z", "2000-08-22T08:00+02:00", "PT6H"),
+      Some(true)
+    );
+    assert_eq!(is_on_grid("2000", "never", "P1Y"), None);
+    assert_eq!(is_on_grid("1990", "1995", "P999999Y"), Some(false));
+    assert_eq!(is_on_grid("0", "999999", "P999999Y"), Some(true));
+  }
+
+  #[test]
+  fn test_enumerate() {
+    let days = enumerate("1999-01-01", "2000-08-22", "P1D", 1000).unwrap();
+    assert_eq!(days.len(), 600);
+    assert_eq!(days[59], "1999-03-01");
+    assert_eq!(days.last().unwrap(), "2000-08-22");
+
+    let years = enumerate("1990", "2000", "P1Y", 1000).unwrap();
+    assert_eq!(years.len(), 11);
+    assert_eq!(years[1], "1991");
+
+    assert_eq!(
+      enumerate("2000-01-31", "2000-04-30", "P1M", 1000).unwrap(),
+      vec!["2000-01-31", "2000-02-29", "2000-03-31", "2000-04-30"]
+    );
+    assert_eq!(
+      enumerate("2000-08-22T18:00Z", "2000-08-23T06:00Z", "PT6H", 1000).unwrap(),
+      vec![
+        "2000-08-22T18:00Z",
+        "2000-08-23T00:00Z",
+        "2000-08-23T06:00Z"
+      ]
+    );
+    assert_eq!(
+      enumerate("2000-08-22", "2000-08-22T12:00Z", "PT6H", 1000).unwrap(),
+      vec!["2000-08-22T00Z", "2000-08-22T06Z", "2000-08-22T12Z"]
+    );
+    assert!(enumerate("2000", "1990", "P1Y", 1000).unwrap().is_empty());
+    assert!(enumerate("2000", "2001", "P0Y", 1000).is_err());
+    assert!(enumerate("2000", "never", "P1Y", 1000).is_err());
+    assert_eq!(enumerate("1990", "2000", "P1Y", 11).unwrap().len(), 11);
+    assert!(enumerate("1990", "2000", "P1Y", 10).is_err());
+
+    // Extents beyond the largest year are refused or end there, rather than overflow
+    assert!(enumerate("1990", "2000", "P9999999999Y", 1000).is_err());
+    assert!(enumerate("1990", "99999999999", "P1Y", 1000).is_err());
+    assert_eq!(
+      enumerate("0", "999999", "P999999Y", 1000).unwrap(),
+      vec!["0000", "999999"]
+    );
+    assert_eq!(
+      enumerate("999999", "999999-12-31", "P999999YT1S", 1000).unwrap(),
+      vec!["999999-01-01T00:00:00Z"]
+    );
+  }
+}
@@ -0,0 +1,100 @@
+//! JavaScript bindings for the WMS client, usable from a page or a service worker.
+//!
+//! The service is exported as `WebMappingService` and its requests return Promises:
+//! ```js
+//! const wms = new WebMappingService("https://ows.terrestris.de/osm/service");
+//! const capabilities = await wms.getCapabilities();
+//! const png = await wms.getMap({
+//!   layers: ["OSM-WMS"],
+//!   srs: "EPSG:4326",
+//!   bbox: { minx: -180, miny: -90, maxx: 180, maxy: 90, SRS: "EPSG:4326" },
+//! });
+//! ```
+use super::{GetMapParameters, WebMappingService, Wms};
+use crate::http::FetchClient;
+use js_sys::{Promise, Uint8Array, JSON};
+use serde::{de::DeserializeOwned, Serialize};
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::future_to_promise;
+
+/// A `WebMappingService` using the browser's `fetch`, exported to JavaScript
+#[wasm_bindgen(js_name = WebMappingService)]
+pub struct JsWebMappingService {
+  inner: Rc<RefCell<WebMappingService<FetchClient>>>,
+}
+
+#[wasm_bindgen(js_class = WebMappingService)]
+impl JsWebMappingService {
+  /// Use the given URL as the endpoint, as `WebMappingService::from_url` does
+  #[wasm_bindgen(constructor)]
+  pub fn new(url: String) -> Result<JsWebMappingService, JsValue> {
+    let wms = WebMappingService::from_url(url).map_err(to_js)?;
+    Ok(JsWebMappingService {
+      inner: Rc::new(RefCell::new(wms)),
+    })
+  }
+
+  /// The negotiated version, or the proposed one until the capabilities have been loaded
+  #[wasm_bindgen(getter)]
+  pub fn version(&self) -> String {
+    self.inner.borrow().version.clone()
+  }
+
+  /// Resolves to the capabilities, as an object mirroring `GetCapabilities`
+  #[wasm_bindgen(js_name = getCapabilities)]
+  pub fn get_capabilities(&self) -> Promise {
+    let inner = self.inner.clone();
+    future_to_promise(async move {
+      // Work on a copy so that no borrow is held across the request
+      let mut wms = inner.borrow().clone();
+      let capabilities = wms.get_capabilities().await.map_err(to_js)?;
+      keep_negotiated(&inner, wms);
+      to_object(&capabilities)
+    })
+  }
+
+  /// Resolves to the image as a `Uint8Array`. The parameters are an object mirroring
+  /// `GetMapParameters`; missing fields take their default values.
+  #[wasm_bindgen(js_name = getMap)]
+  pub fn get_map(&self, params: JsValue) -> Promise {
+    let inner = self.inner.clone();
+    future_to_promise(async move {
+      let params: GetMapParameters = from_object(&params)?;
+      let mut wms = inner.borrow().clone();
+      let bytes = wms.get_map(params).await.map_err(to_js)?;
+      keep_negotiated(&inner, wms);
+      Ok(Uint8Array::from(bytes.as_slice()).into())
+    })
+  }
+}
+
+/// Keep the negotiated version and capabilities of a copy of the service for later calls,
+/// unless an overlapping call has stored its own already
+fn keep_negotiated(
+  inner: &RefCell<WebMappingService<FetchClient>>,
+  wms: WebMappingService<FetchClient>,
+) {
+  let mut inner = inner.borrow_mut();
+  if inner.capabilities.is_none() && wms.capabilities.is_some() {
+    inner.version = wms.version;
+    inner.raw_xml = wms.raw_xml;
+    inner.capabilities = wms.capabilities;
+    inner.cache = wms.cache;
+  }
+}
+
+fn to_js(e: crate::Error) -> JsValue {
+  js_sys::Error::new(&e.to_string()).into()
+}
+
+fn to_object<T: Serialize>(value: &T) -> Result<JsValue, JsValue> {
+  let json = serde_json::to_string(value).map_err(|e| to_js(e.into()))?;
+  JSON::parse(&json)
+}
+
+fn from_object<T: DeserializeOwned>(value: &JsValue) -> Result<T, JsValue> {
+  let json = String::from(JSON::stringify(value)?);
+  serde_json::from_str(&json).map_err(|e| to_js(e.into()))
+}
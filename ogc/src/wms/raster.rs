@@ -0,0 +1,150 @@
+//! Decoded GetMap responses, available with the `image` feature.
+//!
+//! `WebMappingService::get_map_image` decodes the response and checks it has the requested
+//! size, so that maps can be inspected (e.g. for blank tiles) and composed client-side.
+use super::{GetMapParameters, WebMappingService};
+use crate::error::Error;
+use crate::http::HttpClient;
+use image::{imageops, DynamicImage, ImageFormat, Rgba};
+
+/// A map image as decoded from a GetMap response
+#[derive(Clone, Debug, PartialEq)]
+pub struct MapImage {
+  /// The format the server encoded the image in
+  pub format: ImageFormat,
+  pub image: DynamicImage,
+}
+
+impl MapImage {
+  /// Decode the image, recognising the format by its signature or else by the MIME type
+  pub fn decode(bytes: &[u8], mime_type: &str) -> crate::Result<Self> {
+    let format = image::guess_format(bytes)
+      .ok()
+      .or_else(|| ImageFormat::from_mime_type(mime_type))
+      .ok_or_else(|| Error::UnsupportedContentType(mime_type.to_string()))?;
+    Ok(MapImage {
+      format,
+      image: image::load_from_memory_with_format(bytes, format)?,
+    })
+  }
+
+  pub fn width(&self) -> u32 {
+    self.image.width()
+  }
+
+  pub fn height(&self) -> u32 {
+    self.image.height()
+  }
+
+  /// The colour of every pixel, if they all have the same one. Fully transparent pixels
+  /// count as the same colour whatever their colour channels.
+  pub fn single_colour(&self) -> Option<Rgba<u8>> {
+    let image = self.image.to_rgba8();
+    let normalise = |p: &Rgba<u8>| if p[3] == 0 { Rgba([0, 0, 0, 0]) } else { *p };
+    let mut pixels = image.pixels().map(normalise);
+    let first = pixels.next()?;
+    if pixels.all(|p| p == first) {
+      Some(first)
+    } else {
+      None
+    }
+  }
+
+  /// Whether the map is blank, i.e. of a single colour as servers render areas without data
+  pub fn is_blank(&self) -> bool {
+    self.single_colour().is_some()
+  }
+
+  /// Draw `top` over this map, blending by its alpha channel, e.g. to compose layers
+  /// requested separately. The result is RGBA.
+  pub fn overlay(&mut self, top: &MapImage) {
+    let mut image = self.image.to_rgba8();
+    imageops::overlay(&mut image, &top.image.to_rgba8(), 0, 0);
+    self.image = DynamicImage::ImageRgba8(image);
+  }
+}
+
+impl<C: HttpClient> WebMappingService<C> {
+  /// Perform the GetMap request and decode the image, checking it has the requested size
+  pub async fn get_map_image(&self, req: &GetMapParameters) -> crate::Result<MapImage> {
+    let bytes = self.fetch_map(req).await?;
+    let map = MapImage::decode(&bytes, &req.format)?;
+    let expected = (u32::from(req.width), u32::from(req.height));
+    let actual = (map.width(), map.height());
+    if actual != expected {
+      return Err(Error::ImageSize { expected, actual });
+    }
+    Ok(map)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::MapImage;
+  use crate::http::{HttpResponse, MockClient};
+  use crate::wms::{GetMapParameters, WebMappingService};
+  use crate::Error;
+  use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+  use std::io::Cursor;
+
+  fn encode(image: RgbaImage, format: ImageFormat) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    DynamicImage::ImageRgba8(image)
+      .write_to(&mut Cursor::new(&mut bytes), format)
+      .unwrap();
+    bytes
+  }
+
+  #[tokio::test]
+  async fn test_get_map_image() {
+    let png = encode(
+      RgbaImage::from_pixel(250, 250, Rgba([255, 255, 255, 255])),
+      ImageFormat::Png,
+    );
+    let client = MockClient::default()
+      .with_response("GetMap", HttpResponse::ok("image/png", png.clone()))
+      .with_response("GetMap", HttpResponse::ok("image/png", png));
+    let wms =
+      WebMappingService::from_url_with_client("http://hostname/path".to_string(), client).unwrap();
+    let mut req = GetMapParameters {
+      layers: vec!["a".to_string()],
+      ..GetMapParameters::default()
+    };
+    let map = wms.get_map_image(&req).await.unwrap();
+    assert_eq!(map.format, ImageFormat::Png);
+    assert_eq!((map.width(), map.height()), (250, 250));
+    assert!(map.is_blank());
+    assert_eq!(map.single_colour(), Some(Rgba([255, 255, 255, 255])));
+
+    req.width = 500;
+    match wms.get_map_image(&req).await {
+      Err(Error::ImageSize { expected, actual }) => {
+        assert_eq!(expected, (500, 250));
+        assert_eq!(actual, (250, 250));
+      }
+      other => panic!("Unexpected result: {:?}", other),
+    }
+  }
+
+  #[test]
+  fn test_blank_and_overlay() {
+    // Transparent pixels are blank whatever their colour channels
+    let mut image = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 0]));
+    image.put_pixel(1, 1, Rgba([255, 0, 0, 0]));
+    let mut base = MapImage::decode(&encode(image, ImageFormat::Png), "image/png").unwrap();
+    assert_eq!(base.single_colour(), Some(Rgba([0, 0, 0, 0])));
+
+    let mut roads = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 0]));
+    roads.put_pixel(2, 2, Rgba([0, 0, 255, 255]));
+    let roads = MapImage::decode(&encode(roads, ImageFormat::Png), "").unwrap();
+    assert!(!roads.is_blank());
+    base.overlay(&roads);
+    let composed = base.image.to_rgba8();
+    assert_eq!(composed.get_pixel(2, 2), &Rgba([0, 0, 255, 255]));
+    assert_eq!(composed.get_pixel(0, 0)[3], 0);
+
+    // Neither the signature nor the MIME type give a known format
+    let png = encode(RgbaImage::new(4, 4), ImageFormat::Png);
+    assert!(MapImage::decode(&png[8..], "text/plain").is_err());
+  }
+}
@@ -0,0 +1,211 @@
+//! Map scales, as used to limit the range in which a layer is rendered.
+//!
+//! WMS 1.3.0 gives the range as `MinScaleDenominator`/`MaxScaleDenominator`, using the
+//! "standardized rendering pixel size" of 0.28mm. WMS 1.1.x gives a `ScaleHint` instead: the
+//! range of ground distances covered by the diagonal of a pixel, in the units of the CRS.
+use super::{is_lat_lon_crs, BoundingBox, GetCapabilities, GetMapParameters, Layer, ScaleHint};
+
+/// The size of a pixel in metres assumed when computing scale denominators
+pub const STANDARD_PIXEL_SIZE: f64 = 0.00028;
+
+/// The length of the diagonal of a standard pixel, which ScaleHints are measured by
+const PIXEL_DIAGONAL: f64 = STANDARD_PIXEL_SIZE * std::f64::consts::SQRT_2;
+
+/// The ScaleHint bound written for an unbounded end of a range, far beyond the pixel size of
+/// any map: 1.1.x clients may read a bound of 0 literally and never show the layer
+const UNBOUNDED_SCALE_HINT: f64 = 1e12;
+
+/// The metres per degree along the equator, to compute the scale of geographic maps
+const METRES_PER_DEGREE: f64 = 6_378_137.0 * std::f64::consts::PI / 180.0;
+
+/// The scale denominator of a map of `width` pixels covering the bounding box in the CRS.
+/// Projected CRSs are assumed to be in metres and geographic ones are measured along the
+/// equator, as is conventional for WMS.
+pub fn scale_denominator(crs: &str, bbox: &BoundingBox, width: u32) -> f64 {
+  let metres_per_unit = if is_geographic(crs) {
+    METRES_PER_DEGREE
+  } else {
+    1.0
+  };
+  let ground_width = (bbox.maxx - bbox.minx).abs() * metres_per_unit;
+  ground_width / (f64::from(width.max(1)) * STANDARD_PIXEL_SIZE)
+}
+
+fn is_geographic(crs: &str) -> bool {
+  is_lat_lon_crs(crs)
+    || ["CRS:84", "CRS:83", "CRS:27"]
+      .iter()
+      .any(|c| c.eq_ignore_ascii_case(crs))
+}
+
+impl ScaleHint {
+  /// The range as scale denominators, from the ground distance of the pixel diagonals
+  pub fn scale_denominators(&self) -> (f64, f64) {
+    (self.min / PIXEL_DIAGONAL, self.max / PIXEL_DIAGONAL)
+  }
+
+  /// The hint for a range of scale denominators. An unbounded minimum is given as 0 and an
+  /// unbounded maximum as a very large bound.
+  pub fn from_scale_denominators(min: Option<f64>, max: Option<f64>) -> Self {
+    ScaleHint {
+      min: min.unwrap_or(0.0) * PIXEL_DIAGONAL,
+      max: max.map_or(UNBOUNDED_SCALE_HINT, |max| max * PIXEL_DIAGONAL),
+    }
+  }
+}
+
+impl GetMapParameters {
+  /// The scale denominator of the requested map, see `scale_denominator`
+  pub fn scale_denominator(&self) -> f64 {
+    scale_denominator(&self.srs, &self.bbox, u32::from(self.width))
+  }
+}
+
+impl Layer {
+  /// The range of scale denominators the Layer is rendered at, from the scale denominators
+  /// or else the ScaleHint; `None` means that end of the range is unbounded, as does a
+  /// ScaleHint bound of 0 or, for the maximum, of at least the one written for it
+  pub fn scale_denominators(&self) -> (Option<f64>, Option<f64>) {
+    if self.min_scale_denominator.is_some() || self.max_scale_denominator.is_some() {
+      return (self.min_scale_denominator, self.max_scale_denominator);
+    }
+    match &self.scale_hint {
+      Some(hint) => {
+        let (min, max) = hint.scale_denominators();
+        (
+          Some(min).filter(|m| *m > 0.0),
+          Some(max).filter(|_| hint.max > 0.0 && hint.max < UNBOUNDED_SCALE_HINT),
+        )
+      }
+      None => (None, None),
+    }
+  }
+
+  /// Whether the Layer is rendered at the scale denominator: the minimum is inclusive and
+  /// the maximum exclusive, as per section 7.2.4.6.9 of the WMS 1.3.0 specification
+  pub fn is_visible_at(&self, scale_denominator: f64) -> bool {
+    let (min, max) = self.scale_denominators();
+    min.map_or(true, |min| scale_denominator >= min)
+      && max.map_or(true, |max| scale_denominator < max)
+  }
+}
+
+impl GetCapabilities {
+  /// The named layers, resolved as by `resolved_layers`, that are rendered at the scale
+  /// denominator, e.g. that of `GetMapParameters::scale_denominator`
+  pub fn layers_visible_at(&self, scale_denominator: f64) -> Vec<Layer> {
+    self
+      .resolved_layers()
+      .into_iter()
+      .filter(|l| l.is_visible_at(scale_denominator))
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{scale_denominator, PIXEL_DIAGONAL};
+  use crate::wms::tests::example_capabilities;
+  use crate::wms::{BoundingBox, GetMapParameters, ScaleHint};
+
+  #[test]
+  fn test_scale_denominator() {
+    // 1000m across 1000 pixels of 0.28mm is 1:3571
+    let bbox = BoundingBox {
+      srs: "EPSG:26986".to_string(),
+      minx: 230000.0,
+      miny: 900000.0,
+      maxx: 231000.0,
+      maxy: 901000.0,
+      ..BoundingBox::default()
+    };
+    let scale = scale_denominator("EPSG:26986", &bbox, 1000);
+    assert!((scale - 3571.43).abs() < 0.01);
+
+    // The whole world, 256 pixels wide, is the usual web mercator zoom level 0
+    let params = GetMapParameters {
+      srs: "CRS:84".to_string(),
+      width: 256,
+      ..GetMapParameters::default()
+    };
+    assert!((params.scale_denominator() - 559_082_264.03).abs() < 0.01);
+    let scale = scale_denominator("EPSG:4326", &params.bbox, 256);
+    assert!((scale - 559_082_264.03).abs() < 0.01);
+
+    let hint = ScaleHint {
+      min: 0.0,
+      max: PIXEL_DIAGONAL * 10_000.0,
+    };
+    let (min, max) = hint.scale_denominators();
+    assert_eq!(min, 0.0);
+    assert!((max - 10_000.0).abs() < 1e-6);
+  }
+
+  #[tokio::test]
+  async fn test_layers_visible_at_v1_3_0() {
+    let capa = example_capabilities(
+      "1.3.0",
+      &[
+        (
+          "<Name>ROADS_RIVERS</Name>",
+          "<Name>ROADS_RIVERS</Name><MaxScaleDenominator>100000</MaxScaleDenominator>",
+        ),
+        (
+          "<Name>ROADS_1M</Name>",
+          "<Name>ROADS_1M</Name><MinScaleDenominator>5000</MinScaleDenominator>",
+        ),
+      ],
+    )
+    .await;
+    let roads = capa.layer("ROADS_1M").unwrap();
+    assert_eq!(roads.scale_denominators(), (Some(5000.0), None));
+
+    let names = |scale: f64| -> Vec<String> {
+      capa
+        .layers_visible_at(scale)
+        .into_iter()
+        .map(|l| l.name)
+        .filter(|n| n.contains('_'))
+        .collect()
+    };
+    // RIVERS_1M inherits the maximum, which ROADS_1M replaces with its own range
+    let roads = capa
+      .resolved_layers()
+      .into_iter()
+      .find(|l| l.name == "ROADS_1M");
+    assert_eq!(roads.unwrap().scale_denominators(), (Some(5000.0), None));
+    assert_eq!(
+      names(1000.0),
+      vec!["ROADS_RIVERS", "RIVERS_1M", "ozone_image"]
+    );
+    assert_eq!(
+      names(5000.0),
+      vec!["ROADS_RIVERS", "ROADS_1M", "RIVERS_1M", "ozone_image"]
+    );
+    assert_eq!(names(100_000.0), vec!["ROADS_1M", "ozone_image"]);
+    // as do Clouds, Temperature, Pressure and population
+    assert_eq!(capa.layers_visible_at(100_000.0).len(), 6);
+  }
+
+  #[tokio::test]
+  async fn test_scale_hints_v1_1_1() {
+    let capa = example_capabilities("1.1.1", &[]).await;
+    let layer = capa.layer("massgis_dep_21e_mcp").unwrap();
+    assert_eq!(
+      layer.scale_hint,
+      Some(ScaleHint {
+        min: 4000.0,
+        max: 75000.0,
+      })
+    );
+    let (min, max) = layer.scale_denominators();
+    assert!((min.unwrap() - 4000.0 / PIXEL_DIAGONAL).abs() < 1e-6);
+    assert!((max.unwrap() - 75000.0 / PIXEL_DIAGONAL).abs() < 1e-6);
+    assert!(!layer.is_visible_at(1000.0));
+    assert!(layer.is_visible_at(4000.0 / PIXEL_DIAGONAL));
+
+    let all = capa.layers().count();
+    let visible = capa.layers_visible_at(1_000_000_000.0).len();
+    assert!(visible < all);
+  }
+}
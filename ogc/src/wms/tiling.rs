@@ -0,0 +1,255 @@
+//! Maps larger than a service allows in one GetMap request, fetched as a grid of tiles.
+//!
+//! `WebMappingService::tiles` splits the map into requests within the service's `MaxWidth`
+//! and `MaxHeight`. With the `image` feature `WebMappingService::get_map_tiled` also fetches
+//! them concurrently and stitches the results into a single image.
+use super::{BoundingBox, GetMapParameters, WebMappingService};
+use crate::http::HttpClient;
+
+/// The tile size used when the service does not advertise MaxWidth or MaxHeight
+pub const DEFAULT_TILE_SIZE: u32 = 2048;
+
+/// One request of a tiled map
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tile {
+  /// The column of the map the tile starts at, in pixels from the left
+  pub x: u32,
+  /// The row of the map the tile starts at, in pixels from the top
+  pub y: u32,
+  /// The GetMap request for the tile, with its own size and bounding box
+  pub params: GetMapParameters,
+}
+
+/// Split a map of `width` by `height` pixels, covering `req.bbox`, into a row-major grid of
+/// tiles of at most `max_width` by `max_height` pixels. The tiles are of near equal size and
+/// their bounding boxes meet exactly, so that the map can be reassembled without seams.
+/// A map without pixels has no tiles.
+pub fn tile_grid(
+  req: &GetMapParameters,
+  width: u32,
+  height: u32,
+  max_width: u32,
+  max_height: u32,
+) -> Vec<Tile> {
+  if width == 0 || height == 0 {
+    return Vec::new();
+  }
+  let max_width = max_width.clamp(1, u32::from(u16::MAX));
+  let max_height = max_height.clamp(1, u32::from(u16::MAX));
+  let columns = split(width, max_width);
+  let rows = split(height, max_height);
+  let b = &req.bbox;
+  let x_at = |px: u32| b.minx + (b.maxx - b.minx) * f64::from(px) / f64::from(width);
+  // Pixel rows run from the top, i.e. from maxy down
+  let y_at = |px: u32| b.maxy - (b.maxy - b.miny) * f64::from(px) / f64::from(height);
+  let mut tiles = Vec::new();
+  for (y, y_end) in rows.iter().zip(rows.iter().skip(1)) {
+    for (x, x_end) in columns.iter().zip(columns.iter().skip(1)) {
+      tiles.push(Tile {
+        x: *x,
+        y: *y,
+        params: GetMapParameters {
+          width: (x_end - x) as u16,
+          height: (y_end - y) as u16,
+          bbox: BoundingBox {
+            minx: x_at(*x),
+            miny: y_at(*y_end),
+            maxx: x_at(*x_end),
+            maxy: y_at(*y),
+            ..b.clone()
+          },
+          ..req.clone()
+        },
+      });
+    }
+  }
+  tiles
+}
+
+/// The boundaries of the fewest near equal parts of `size` that are at most `max` long
+fn split(size: u32, max: u32) -> Vec<u32> {
+  let parts = (size / max + u32::from(size % max != 0)).max(1);
+  (0..=parts)
+    .map(|i| (u64::from(size) * u64::from(i) / u64::from(parts)) as u32)
+    .collect()
+}
+
+impl<C: HttpClient> WebMappingService<C> {
+  /// The tiles for a map of `width` by `height` pixels covering `req.bbox`, within the
+  /// MaxWidth and MaxHeight of the loaded capabilities or else `DEFAULT_TILE_SIZE`
+  pub fn tiles(&self, req: &GetMapParameters, width: u32, height: u32) -> Vec<Tile> {
+    let service = self.capabilities.as_ref().map(|c| &c.service);
+    let max_width = service.and_then(|s| s.max_width);
+    let max_height = service.and_then(|s| s.max_height);
+    tile_grid(
+      req,
+      width,
+      height,
+      max_width.unwrap_or(DEFAULT_TILE_SIZE),
+      max_height.unwrap_or(DEFAULT_TILE_SIZE),
+    )
+  }
+
+  /// Fetch a map of `width` by `height` pixels, which may exceed what the service allows in
+  /// one request, as tiles (see `tiles`) with at most `concurrency` requests in flight, and
+  /// stitch them together. The size in `req` is ignored; each tile must have the size it
+  /// was requested at. A map without pixels is an `InvalidParameter` error.
+  #[cfg(feature = "image")]
+  pub async fn get_map_tiled(
+    &self,
+    req: &GetMapParameters,
+    width: u32,
+    height: u32,
+    concurrency: usize,
+  ) -> crate::Result<image::RgbaImage> {
+    use futures_util::stream::{self, StreamExt, TryStreamExt};
+
+    if width == 0 || height == 0 {
+      return Err(crate::Error::InvalidParameter(format!(
+        "Cannot fetch a map of {}x{} pixels",
+        width, height
+      )));
+    }
+    let tiles = self.tiles(req, width, height);
+    let mut map = image::RgbaImage::new(width, height);
+    let mut fetched = stream::iter(tiles.iter().map(|tile| async move {
+      let image = self.get_map_image(&tile.params).await?;
+      Ok::<_, crate::Error>((tile, image))
+    }))
+    .buffer_unordered(concurrency.max(1));
+    while let Some((tile, image)) = fetched.try_next().await? {
+      let image = image.image.to_rgba8();
+      image::imageops::replace(&mut map, &image, tile.x.into(), tile.y.into());
+    }
+    Ok(map)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::tile_grid;
+  use crate::wms::{BoundingBox, GetMapParameters, WebMappingService, Wms};
+  use std::fs::read_to_string;
+
+  #[test]
+  fn test_tile_grid() {
+    let req = GetMapParameters {
+      srs: "EPSG:26986".to_string(),
+      bbox: BoundingBox {
+        srs: "EPSG:26986".to_string(),
+        minx: 0.0,
+        miny: 0.0,
+        maxx: 5000.0,
+        maxy: 3000.0,
+        ..BoundingBox::default()
+      },
+      ..GetMapParameters::default()
+    };
+    let tiles = tile_grid(&req, 5000, 3000, 2048, 2048);
+    assert_eq!(tiles.len(), 6);
+    let offsets: Vec<(u32, u32)> = tiles.iter().map(|t| (t.x, t.y)).collect();
+    assert_eq!(
+      offsets,
+      vec![
+        (0, 0),
+        (1666, 0),
+        (3333, 0),
+        (0, 1500),
+        (1666, 1500),
+        (3333, 1500)
+      ]
+    );
+    let sizes: Vec<(u16, u16)> = tiles
+      .iter()
+      .map(|t| (t.params.width, t.params.height))
+      .collect();
+    assert_eq!(sizes[..3], [(1666, 1500), (1667, 1500), (1667, 1500)]);
+
+    // The top left tile is at the top of the bounding box
+    let first = &tiles[0].params.bbox;
+    assert_eq!(
+      (first.minx, first.miny, first.maxx, first.maxy),
+      (0.0, 1500.0, 1666.0, 3000.0)
+    );
+    assert_eq!(first.srs, "EPSG:26986");
+    let last = &tiles[5].params.bbox;
+    assert_eq!(
+      (last.minx, last.miny, last.maxx, last.maxy),
+      (3333.0, 0.0, 5000.0, 1500.0)
+    );
+
+    // A map within the limits is a single tile
+    let tiles = tile_grid(&req, 500, 300, 2048, 2048);
+    assert_eq!(tiles.len(), 1);
+    assert_eq!(tiles[0].params.bbox, req.bbox);
+
+    // and a map without pixels has none
+    assert!(tile_grid(&req, 0, 300, 2048, 2048).is_empty());
+    assert!(tile_grid(&req, 500, 0, 2048, 2048).is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_tiles_within_service_limits() {
+    let xml = read_to_string("./examples/WMS-1.3.0.xml").unwrap();
+    let mut wms = WebMappingService::from_string(xml);
+    let req = GetMapParameters::default();
+    assert_eq!(wms.tiles(&req, 8000, 100).len(), 4);
+    // MaxWidth and MaxHeight are 2048
+    wms.get_capabilities().await.unwrap();
+    assert_eq!(wms.tiles(&req, 8000, 4097).len(), 4 * 3);
+  }
+
+  #[cfg(feature = "image")]
+  #[tokio::test]
+  async fn test_get_map_tiled() {
+    use crate::http::{HttpResponse, MockClient};
+    use image::{ImageFormat, Rgba, RgbaImage};
+    use std::io::Cursor;
+
+    let png = |width: u32, height: u32, shade: u8| {
+      let mut bytes = Vec::new();
+      RgbaImage::from_pixel(width, height, Rgba([shade, 0, 0, 255]))
+        .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+        .unwrap();
+      HttpResponse::ok("image/png", bytes)
+    };
+    // The tiles are requested in row-major order
+    let client = MockClient::default()
+      .with_response("GetMap", png(2000, 1500, 10))
+      .with_response("GetMap", png(2000, 1500, 20))
+      .with_response("GetMap", png(2000, 1500, 30))
+      .with_response("GetMap", png(2000, 1500, 40));
+    let wms =
+      WebMappingService::from_url_with_client("http://hostname/path".to_string(), client.clone())
+        .unwrap();
+    let req = GetMapParameters {
+      layers: vec!["a".to_string()],
+      ..GetMapParameters::default()
+    };
+    let map = wms.get_map_tiled(&req, 4000, 3000, 2).await.unwrap();
+    assert_eq!(map.dimensions(), (4000, 3000));
+    assert_eq!(map.get_pixel(0, 0)[0], 10);
+    assert_eq!(map.get_pixel(3999, 0)[0], 20);
+    assert_eq!(map.get_pixel(0, 2999)[0], 30);
+    assert_eq!(map.get_pixel(2000, 1500)[0], 40);
+
+    let requests = client.requests();
+    assert_eq!(requests.len(), 4);
+    let value = |i: usize, key: &str| {
+      requests[i]
+        .query_pairs()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.into_owned())
+    };
+    assert_eq!(value(0, "WIDTH"), Some("2000".to_string()));
+    assert_eq!(value(0, "BBOX"), Some("-180,0,0,90".to_string()));
+    assert_eq!(value(3, "BBOX"), Some("0,-90,180,0".to_string()));
+
+    // A map without pixels is not requested
+    assert!(matches!(
+      wms.get_map_tiled(&req, 0, 3000, 2).await,
+      Err(crate::Error::InvalidParameter(_))
+    ));
+    assert_eq!(client.requests().len(), 4);
+  }
+}
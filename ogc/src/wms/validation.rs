@@ -0,0 +1,575 @@
+//! Client-side validation of requests against the capabilities of the service, so that
+//! requests the server would reject (or silently alter) are caught before they are sent.
+use super::{
+  is_lat_lon_crs, is_v1_3, BoundingBox, Dimension, GetCapabilities, GetFeatureInfoParameters,
+  GetMapParameters, Layer,
+};
+use std::fmt;
+
+/// A way in which a request does not conform to the capabilities of the service
+#[derive(Clone, Debug, PartialEq)]
+pub enum Violation {
+  /// No named layer of the service has this name
+  LayerNotDefined(String),
+  /// The layer is not offered in this style
+  StyleNotDefined { layer: String, style: String },
+  /// STYLES must be empty or give one style, possibly empty, per layer
+  StyleCount { layers: usize, styles: usize },
+  /// The layer is not offered in this CRS (SRS in 1.1.x)
+  InvalidCRS { layer: String, crs: String },
+  /// GetMap is not offered in this format
+  InvalidFormat(String),
+  /// The map is wider than the service allows
+  MaxWidth(u32),
+  /// The map is higher than the service allows
+  MaxHeight(u32),
+  /// More layers were requested than the service allows
+  LayerLimit { layers: usize, limit: u32 },
+  /// The value of the dimension, e.g. TIME, is not within the extent declared for the layer
+  InvalidDimensionValue {
+    layer: String,
+    dimension: String,
+    value: String,
+  },
+  /// The layer requires a value for the dimension, as it declares no default
+  MissingDimensionValue { layer: String, dimension: String },
+  /// The layer only supports maps of this size; 0 means any size in that dimension
+  FixedSize {
+    layer: String,
+    width: u32,
+    height: u32,
+  },
+  /// The layer can only be requested for its full bounding box, given in x/y order
+  NoSubsets { layer: String, bbox: BoundingBox },
+  /// GetFeatureInfo is not supported for the layer
+  LayerNotQueryable(String),
+}
+
+impl fmt::Display for Violation {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Violation::LayerNotDefined(layer) => write!(f, "Layer {} is not defined", layer),
+      Violation::StyleNotDefined { layer, style } => {
+        write!(f, "Layer {} is not offered in style {}", layer, style)
+      }
+      Violation::StyleCount { layers, styles } => {
+        write!(f, "{} styles were given for {} layers", styles, layers)
+      }
+      Violation::InvalidCRS { layer, crs } => {
+        write!(f, "Layer {} is not offered in CRS {}", layer, crs)
+      }
+      Violation::InvalidFormat(format) => write!(f, "Format {} is not offered", format),
+      Violation::MaxWidth(max) => write!(f, "The map width must not exceed {}", max),
+      Violation::MaxHeight(max) => write!(f, "The map height must not exceed {}", max),
+      Violation::LayerLimit { layers, limit } => write!(
+        f,
+        "{} layers were requested but at most {} are allowed",
+        layers, limit
+      ),
+      Violation::InvalidDimensionValue {
+        layer,
+        dimension,
+        value,
+      } => write!(
+        f,
+        "Value {} is not within the {} extent of layer {}",
+        value, dimension, layer
+      ),
+      Violation::MissingDimensionValue { layer, dimension } => write!(
+        f,
+        "Layer {} requires a {} value as it has no default",
+        layer, dimension
+      ),
+      Violation::FixedSize {
+        layer,
+        width,
+        height,
+      } => write!(
+        f,
+        "Layer {} must be requested with a fixed size of {}x{}",
+        layer, width, height
+      ),
+      Violation::NoSubsets { layer, bbox } => write!(
+        f,
+        "Layer {} must be requested for its full bounding box {}",
+        layer,
+        bbox.to_str()
+      ),
+      Violation::LayerNotQueryable(layer) => write!(f, "Layer {} is not queryable", layer),
+    }
+  }
+}
+
+impl GetCapabilities {
+  /// Check the GetMap parameters against these capabilities, taking inherited layer
+  /// properties into account. Every violation found is returned, so an empty list means the
+  /// request conforms to the capabilities.
+  pub fn validate_get_map(&self, req: &GetMapParameters) -> Vec<Violation> {
+    self.validate_map(&self.resolved_layers(), req)
+  }
+
+  /// Check the GetFeatureInfo parameters, including the map they refer to, against these
+  /// capabilities; see `validate_get_map`
+  pub fn validate_get_feature_info(&self, req: &GetFeatureInfoParameters) -> Vec<Violation> {
+    let layers = self.resolved_layers();
+    let mut violations = self.validate_map(&layers, &req.map);
+    for name in req.query_layers.iter() {
+      if let Some(layer) = layers.iter().find(|l| &l.name == name) {
+        if !layer.queryable() {
+          violations.push(Violation::LayerNotQueryable(name.clone()));
+        }
+      }
+    }
+    violations
+  }
+
+  fn validate_map(&self, layers: &[Layer], req: &GetMapParameters) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    if !self.capability.request.get_map.supports_format(&req.format) {
+      violations.push(Violation::InvalidFormat(req.format.clone()));
+    }
+    if let Some(max) = self.service.max_width {
+      if u32::from(req.width) > max {
+        violations.push(Violation::MaxWidth(max));
+      }
+    }
+    if let Some(max) = self.service.max_height {
+      if u32::from(req.height) > max {
+        violations.push(Violation::MaxHeight(max));
+      }
+    }
+    if let Some(limit) = self.service.layer_limit {
+      if req.layers.len() > limit as usize {
+        violations.push(Violation::LayerLimit {
+          layers: req.layers.len(),
+          limit,
+        });
+      }
+    }
+    if !req.styles.is_empty() && req.styles.len() != req.layers.len() {
+      violations.push(Violation::StyleCount {
+        layers: req.layers.len(),
+        styles: req.styles.len(),
+      });
+    }
+    for (i, name) in req.layers.iter().enumerate() {
+      let layer = match layers.iter().find(|l| &l.name == name) {
+        Some(layer) => layer,
+        None => {
+          violations.push(Violation::LayerNotDefined(name.clone()));
+          continue;
+        }
+      };
+      if let Some(style) = req.styles.get(i).filter(|s| !s.is_empty()) {
+        if layer.style(style).is_none() {
+          violations.push(Violation::StyleNotDefined {
+            layer: name.clone(),
+            style: style.clone(),
+          });
+        }
+      }
+      if !layer.supports_crs(&req.srs) {
+        violations.push(Violation::InvalidCRS {
+          layer: name.clone(),
+          crs: req.srs.clone(),
+        });
+      }
+      let dimensions = layer.dimensions();
+      for (dimension, value) in [("time", &req.time), ("elevation", &req.elevation)].iter() {
+        if let Some(violation) = check_dimension(name, &dimensions, dimension, value.as_deref()) {
+          violations.push(violation);
+        }
+      }
+      let (fixed_width, fixed_height) = (layer.fixed_width(), layer.fixed_height());
+      let width_ok = fixed_width == 0 || u32::from(req.width) == fixed_width;
+      let height_ok = fixed_height == 0 || u32::from(req.height) == fixed_height;
+      if !width_ok || !height_ok {
+        violations.push(Violation::FixedSize {
+          layer: name.clone(),
+          width: fixed_width,
+          height: fixed_height,
+        });
+      }
+      if layer.no_subsets() {
+        // The axis order of the layer's bounding boxes is that of the capabilities, while
+        // the request's is always x/y and only swapped when it is sent
+        if let Some(bbox) = full_extent(layer, &req.srs, &self.version) {
+          if !same_bbox(&bbox, &req.bbox) {
+            violations.push(Violation::NoSubsets {
+              layer: name.clone(),
+              bbox,
+            });
+          }
+        }
+      }
+    }
+    violations
+  }
+}
+
+/// Check the value requested for the dimension against the layer's declaration of it, if any
+fn check_dimension(
+  layer: &str,
+  dimensions: &[Dimension],
+  name: &str,
+  value: Option<&str>,
+) -> Option<Violation> {
+  // A layer may repeat a dimension, e.g. to override its units; prefer one with an extent
+  let matching: Vec<&Dimension> = dimensions
+    .iter()
+    .filter(|d| d.name.eq_ignore_ascii_case(name))
+    .collect();
+  let dimension = matching
+    .iter()
+    .rev()
+    .find(|d| !d.extent.trim().is_empty())
+    .or_else(|| matching.last())?;
+  match value {
+    Some(value) if !dimension.contains(value) => Some(Violation::InvalidDimensionValue {
+      layer: layer.to_string(),
+      dimension: dimension.name.clone(),
+      value: value.to_string(),
+    }),
+    None if dimension.default.is_none() => Some(Violation::MissingDimensionValue {
+      layer: layer.to_string(),
+      dimension: dimension.name.clone(),
+    }),
+    _ => None,
+  }
+}
+
+/// The layer's bounding box in the CRS, in x/y order like `GetMapParameters::bbox`, from
+/// capabilities of the given version
+fn full_extent(layer: &Layer, crs: &str, version: &str) -> Option<BoundingBox> {
+  if let Some(b) = layer.bbox.iter().find(|b| b.srs.eq_ignore_ascii_case(crs)) {
+    // 1.3.0 documents give the bounding box in the axis order of the CRS
+    return Some(if is_v1_3(version) && is_lat_lon_crs(crs) {
+      BoundingBox {
+        srs: b.srs.clone(),
+        minx: b.miny,
+        miny: b.minx,
+        maxx: b.maxy,
+        maxy: b.maxx,
+        resx: b.resy,
+        resy: b.resx,
+      }
+    } else {
+      b.clone()
+    });
+  }
+  let geographic = crs.eq_ignore_ascii_case("CRS:84") || crs.eq_ignore_ascii_case("EPSG:4326");
+  layer
+    .geographic_bbox()
+    .filter(|_| geographic)
+    .map(|b| BoundingBox {
+      srs: crs.to_string(),
+      minx: b.minx,
+      miny: b.miny,
+      maxx: b.maxx,
+      maxy: b.maxy,
+      resx: None,
+      resy: None,
+    })
+}
+
+fn same_bbox(a: &BoundingBox, b: &BoundingBox) -> bool {
+  let close = |x: f64, y: f64| (x - y).abs() <= 1e-6 * x.abs().max(y.abs()).max(1.0);
+  close(a.minx, b.minx) && close(a.miny, b.miny) && close(a.maxx, b.maxx) && close(a.maxy, b.maxy)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Violation;
+  use crate::wms::tests::{example_capabilities, parse_capabilities};
+  use crate::wms::{BoundingBox, GetFeatureInfoParameters, GetMapParameters};
+
+  #[tokio::test]
+  async fn test_fixed_size() {
+    let capa = example_capabilities("1.3.0", &[]).await;
+    let mut req = GetMapParameters {
+      layers: vec!["ozone_image".to_string()],
+      srs: "CRS:84".to_string(),
+      width: 512,
+      height: 256,
+      ..GetMapParameters::default()
+    };
+    assert_eq!(capa.validate_get_map(&req), vec![]);
+    req.height = 512;
+    let violations = capa.validate_get_map(&req);
+    assert_eq!(
+      violations,
+      vec![Violation::FixedSize {
+        layer: "ozone_image".to_string(),
+        width: 512,
+        height: 256,
+      }]
+    );
+    assert_eq!(
+      violations[0].to_string(),
+      "Layer ozone_image must be requested with a fixed size of 512x256"
+    );
+
+    // ozone_image cannot be subset either
+    req.height = 256;
+    req.bbox.maxx = 0.0;
+    assert_eq!(
+      capa.validate_get_map(&req),
+      vec![Violation::NoSubsets {
+        layer: "ozone_image".to_string(),
+        bbox: BoundingBox {
+          srs: "CRS:84".to_string(),
+          ..BoundingBox::default()
+        },
+      }]
+    );
+  }
+
+  #[tokio::test]
+  async fn test_get_map_violations() {
+    let capa = example_capabilities("1.3.0", &[]).await;
+    let mut req = GetMapParameters {
+      layers: vec![
+        "ROADS_1M".to_string(),
+        "Pressure".to_string(),
+        "ROADS_RIVERS".to_string(),
+      ],
+      styles: vec!["ATLAS".to_string(), "".to_string(), "USGS".to_string()],
+      srs: "EPSG:26986".to_string(),
+      width: 2048,
+      height: 1024,
+      format: "image/gif".to_string(),
+      time: Some("2000-01-01/2000-08-22".to_string()),
+      elevation: Some("3000".to_string()),
+      ..GetMapParameters::default()
+    };
+    assert_eq!(
+      capa.validate_get_map(&req),
+      vec![Violation::InvalidCRS {
+        layer: "Pressure".to_string(),
+        crs: "EPSG:26986".to_string(),
+      }]
+    );
+    req.srs = "CRS:84".to_string();
+    assert_eq!(capa.validate_get_map(&req), vec![]);
+
+    // Every violation is reported, not just the first
+    req.layers.extend(vec!["roads".to_string(); 14]);
+    req.styles[2] = "ATLAS".to_string();
+    req.srs = "EPSG:26986".to_string();
+    req.width = 4096;
+    req.format = "image/tiff".to_string();
+    req.time = Some("2001-01-01".to_string());
+    req.elevation = None;
+    let violations = capa.validate_get_map(&req);
+    assert_eq!(
+      violations[..7],
+      vec![
+        Violation::InvalidFormat("image/tiff".to_string()),
+        Violation::MaxWidth(2048),
+        Violation::LayerLimit {
+          layers: 17,
+          limit: 16,
+        },
+        Violation::StyleCount {
+          layers: 17,
+          styles: 3,
+        },
+        Violation::InvalidCRS {
+          layer: "Pressure".to_string(),
+          crs: "EPSG:26986".to_string(),
+        },
+        Violation::InvalidDimensionValue {
+          layer: "Pressure".to_string(),
+          dimension: "time".to_string(),
+          value: "2001-01-01".to_string(),
+        },
+        Violation::StyleNotDefined {
+          layer: "ROADS_RIVERS".to_string(),
+          style: "ATLAS".to_string(),
+        },
+      ][..]
+    );
+    assert_eq!(violations.len(), 7 + 14);
+    assert_eq!(
+      violations[7],
+      Violation::LayerNotDefined("roads".to_string())
+    );
+    assert_eq!(
+      violations[5].to_string(),
+      "Value 2001-01-01 is not within the time extent of layer Pressure"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_attributes_replace_inherited_ones() {
+    let xml = r#"<WMT_MS_Capabilities version="1.1.1">
+      <Service><Name>OGC:WMS</Name><Title>Tiles</Title></Service>
+      <Capability>
+        <Request>
+          <GetCapabilities><Format>application/vnd.ogc.wms_xml</Format></GetCapabilities>
+          <GetMap><Format>image/png</Format></GetMap>
+        </Request>
+        <Layer queryable="1" fixedWidth="256" fixedHeight="256">
+          <Name>tiles</Name>
+          <Title>Tiles</Title>
+          <SRS>EPSG:4326</SRS>
+          <Layer queryable="0" fixedWidth="0" fixedHeight="0">
+            <Name>any_size</Name>
+            <Title>Any size</Title>
+          </Layer>
+          <Layer>
+            <Name>tile</Name>
+            <Title>Tile</Title>
+          </Layer>
+        </Layer>
+      </Capability>
+    </WMT_MS_Capabilities>"#;
+    let capa = parse_capabilities(xml).await;
+    let layers = capa.resolved_layers();
+    let any_size = layers.iter().find(|l| l.name == "any_size").unwrap();
+    assert_eq!(any_size.queryable, Some(false));
+    assert_eq!((any_size.fixed_width(), any_size.fixed_height()), (0, 0));
+    let tile = layers.iter().find(|l| l.name == "tile").unwrap();
+    assert!(tile.queryable());
+    assert_eq!((tile.fixed_width(), tile.fixed_height()), (256, 256));
+
+    let req = GetFeatureInfoParameters {
+      map: GetMapParameters {
+        version: "1.1.1".to_string(),
+        layers: vec!["any_size".to_string()],
+        srs: "EPSG:4326".to_string(),
+        width: 1024,
+        height: 512,
+        ..GetMapParameters::default()
+      },
+      query_layers: vec!["any_size".to_string()],
+      ..GetFeatureInfoParameters::default()
+    };
+    assert_eq!(
+      capa.validate_get_feature_info(&req),
+      vec![Violation::LayerNotQueryable("any_size".to_string())]
+    );
+
+    // The explicit values are written back out
+    let xml = capa.to_xml().unwrap();
+    assert!(xml.contains(r#"<Layer queryable="0" fixedWidth="0" fixedHeight="0">"#));
+  }
+
+  #[tokio::test]
+  async fn test_no_subsets_and_queryable() {
+    let xml = r#"<WMT_MS_Capabilities version="1.1.1">
+      <Service><Name>OGC:WMS</Name><Title>Static maps</Title></Service>
+      <Capability>
+        <Request>
+          <GetCapabilities><Format>application/vnd.ogc.wms_xml</Format></GetCapabilities>
+          <GetMap><Format>image/png</Format></GetMap>
+        </Request>
+        <Layer noSubsets="1">
+          <Title>Root</Title>
+          <SRS>EPSG:4326</SRS>
+          <SRS>EPSG:26986</SRS>
+          <LatLonBoundingBox minx="-180" miny="-90" maxx="180" maxy="90"/>
+          <Layer queryable="1">
+            <Name>static</Name>
+            <Title>Static</Title>
+            <BoundingBox SRS="EPSG:26986" minx="189000" miny="834000" maxx="285000" maxy="962000"/>
+          </Layer>
+          <Layer>
+            <Name>picture</Name>
+            <Title>Picture</Title>
+            <Dimension name="time" units="ISO8601"/>
+            <Extent name="time">2020-01-01/2020-12-31/P1M</Extent>
+          </Layer>
+        </Layer>
+      </Capability>
+    </WMT_MS_Capabilities>"#;
+    let capa = parse_capabilities(xml).await;
+    let mut req = GetFeatureInfoParameters {
+      map: GetMapParameters {
+        version: "1.1.1".to_string(),
+        layers: vec!["static".to_string(), "picture".to_string()],
+        srs: "EPSG:4326".to_string(),
+        ..GetMapParameters::default()
+      },
+      query_layers: vec!["static".to_string(), "picture".to_string()],
+      ..GetFeatureInfoParameters::default()
+    };
+    assert_eq!(
+      capa.validate_get_feature_info(&req),
+      vec![
+        Violation::MissingDimensionValue {
+          layer: "picture".to_string(),
+          dimension: "time".to_string(),
+        },
+        Violation::LayerNotQueryable("picture".to_string())
+      ]
+    );
+    req.map.time = Some("2020-03-01".to_string());
+    assert_eq!(
+      capa.validate_get_feature_info(&req),
+      vec![Violation::LayerNotQueryable("picture".to_string())]
+    );
+
+    req.map.srs = "EPSG:26986".to_string();
+    let violations = capa.validate_get_map(&req.map);
+    assert_eq!(
+      violations,
+      vec![Violation::NoSubsets {
+        layer: "static".to_string(),
+        bbox: BoundingBox {
+          srs: "EPSG:26986".to_string(),
+          minx: 189000.0,
+          miny: 834000.0,
+          maxx: 285000.0,
+          maxy: 962000.0,
+          ..BoundingBox::default()
+        },
+      }]
+    );
+    req.map.bbox = match &violations[0] {
+      Violation::NoSubsets { bbox, .. } => bbox.clone(),
+      _ => unreachable!(),
+    };
+    assert_eq!(capa.validate_get_map(&req.map), vec![]);
+  }
+
+  #[tokio::test]
+  async fn test_no_subsets_across_versions() {
+    let xml = r#"<WMS_Capabilities version="1.3.0">
+      <Service><Name>WMS</Name><Title>Static maps</Title></Service>
+      <Capability>
+        <Request>
+          <GetCapabilities><Format>text/xml</Format></GetCapabilities>
+          <GetMap><Format>image/png</Format></GetMap>
+        </Request>
+        <Layer noSubsets="1">
+          <Name>static</Name>
+          <Title>Static</Title>
+          <CRS>EPSG:4326</CRS>
+          <BoundingBox CRS="EPSG:4326" minx="41" miny="-73" maxx="43" maxy="-69"/>
+        </Layer>
+      </Capability>
+    </WMS_Capabilities>"#;
+    let v1_3 = parse_capabilities(xml).await;
+    let v1_1 = v1_3.to_version("1.1.1").unwrap();
+    let mut req = GetMapParameters {
+      layers: vec!["static".to_string()],
+      srs: "EPSG:4326".to_string(),
+      bbox: BoundingBox {
+        srs: "EPSG:4326".to_string(),
+        minx: -73.0,
+        miny: 41.0,
+        maxx: -69.0,
+        maxy: 43.0,
+        ..BoundingBox::default()
+      },
+      ..GetMapParameters::default()
+    };
+    // The request's bounding box is the layer's whatever the versions
+    for version in ["1.1.1", "1.3.0"] {
+      req.version = version.to_string();
+      assert_eq!(v1_3.validate_get_map(&req), vec![]);
+      assert_eq!(v1_1.validate_get_map(&req), vec![]);
+    }
+  }
+}
@@ -0,0 +1,112 @@
+//! WMS version negotiation, as per section 6.2.4 of the WMS 1.3.0 specification.
+//!
+//! The client requests the highest version it supports. The server answers with that
+//! version, or the highest version it supports below it, or its lowest version when all
+//! of its versions are higher. The client then either accepts the answer, retries with a
+//! lower version it supports, or gives up.
+use xml::reader::{EventReader, XmlEvent};
+
+/// The WMS versions supported by this crate, highest first
+pub const SUPPORTED_VERSIONS: [&str; 3] = ["1.3.0", "1.1.1", "1.1.0"];
+
+/// The next step of the negotiation, given the server's answer
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Negotiation {
+  /// The answered version is supported and should be used for all further requests
+  Accept(String),
+  /// Request the capabilities again with this version
+  Retry(&'static str),
+  /// There is no version both parties support
+  Fail,
+}
+
+/// Decide how to continue after requesting `requested` and receiving `answered`
+pub(crate) fn negotiate(requested: &str, answered: &str) -> Negotiation {
+  if answered.is_empty() || SUPPORTED_VERSIONS.contains(&answered) {
+    let version = if answered.is_empty() {
+      requested
+    } else {
+      answered
+    };
+    return Negotiation::Accept(version.to_string());
+  }
+  let answered = parse(answered);
+  if answered > parse(requested) {
+    // The server's lowest version is above anything we could ask for
+    return Negotiation::Fail;
+  }
+  SUPPORTED_VERSIONS
+    .iter()
+    .find(|v| parse(v) < answered)
+    .map_or(Negotiation::Fail, |v| Negotiation::Retry(v))
+}
+
+/// Read the `version` attribute of the capabilities document's root element
+pub(crate) fn capabilities_version(xml: &str) -> Option<String> {
+  root_attribute(xml, "version")
+}
+
+/// Read an attribute of the document's root element
+pub(crate) fn root_attribute(xml: &str, name: &str) -> Option<String> {
+  for event in EventReader::new(xml.as_bytes()) {
+    match event {
+      Ok(XmlEvent::StartElement { attributes, .. }) => {
+        return attributes
+          .into_iter()
+          .find(|a| a.name.local_name == name)
+          .map(|a| a.value);
+      }
+      Ok(_) => continue,
+      Err(_) => return None,
+    }
+  }
+  None
+}
+
+fn parse(version: &str) -> Vec<u32> {
+  version
+    .split('.')
+    .map(|n| n.trim().parse().unwrap_or(0))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{capabilities_version, negotiate, Negotiation};
+  use std::fs::read_to_string;
+
+  #[test]
+  fn test_negotiate() {
+    assert_eq!(
+      negotiate("1.3.0", "1.3.0"),
+      Negotiation::Accept("1.3.0".to_string())
+    );
+    assert_eq!(
+      negotiate("1.3.0", "1.1.1"),
+      Negotiation::Accept("1.1.1".to_string())
+    );
+    assert_eq!(
+      negotiate("1.3.0", "1.1.0"),
+      Negotiation::Accept("1.1.0".to_string())
+    );
+    assert_eq!(
+      negotiate("1.3.0", ""),
+      Negotiation::Accept("1.3.0".to_string())
+    );
+    assert_eq!(negotiate("1.3.0", "1.2.0"), Negotiation::Retry("1.1.1"));
+    assert_eq!(negotiate("1.3.0", "1.1.5"), Negotiation::Retry("1.1.1"));
+    assert_eq!(negotiate("1.3.0", "1.0.0"), Negotiation::Fail);
+    assert_eq!(negotiate("1.3.0", "2.0.0"), Negotiation::Fail);
+    assert_eq!(negotiate("1.1.1", "1.2.0"), Negotiation::Fail);
+  }
+
+  #[test]
+  fn test_capabilities_version() {
+    let xml = read_to_string("./examples/WMS-1.1.1.xml").unwrap();
+    assert_eq!(capabilities_version(&xml), Some("1.1.1".to_string()));
+    let xml = read_to_string("./examples/WMS-1.3.0.xml").unwrap();
+    assert_eq!(capabilities_version(&xml), Some("1.3.0".to_string()));
+    assert_eq!(capabilities_version("<ServiceExceptionReport/>"), None);
+    assert_eq!(capabilities_version("not xml"), None);
+  }
+}
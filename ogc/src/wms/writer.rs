@@ -0,0 +1,595 @@
+//! Writing capabilities documents, e.g. to republish a service's capabilities after
+//! rewriting its URLs or filtering its layers.
+//!
+//! `GetCapabilities::to_xml` writes a `WMS_Capabilities` document in the WMS namespace for
+//! 1.3.0, and a `WMT_MS_Capabilities` document with the DTD declaration for 1.1.x. Only the
+//! elements the version defines are written: a 1.3.0 document gets the `CRS`,
+//! `EX_GeographicBoundingBox` and scale denominators of the layers, a 1.1.x one their `SRS`,
+//! `LatLonBoundingBox`, `Extent` and `ScaleHint`.
+use super::{
+  Attribution, BoundingBox, Capability, ContactInformation, Dimension, GetCapabilities,
+  KeywordList, Layer, LegendUrl, OnlineResource, Operation, ResourceUrl, Service, Style,
+};
+use crate::error::Error;
+use xml::writer::{EmitterConfig, EventWriter, XmlEvent};
+
+const WMS_NAMESPACE: &str = "http://www.opengis.net/wms";
+const WMS_SCHEMA_LOCATION: &str =
+  "http://www.opengis.net/wms http://schemas.opengis.net/wms/1.3.0/capabilities_1_3_0.xsd";
+const SLD_NAMESPACE: &str = "http://www.opengis.net/sld";
+const XLINK_NAMESPACE: &str = "http://www.w3.org/1999/xlink";
+const XSI_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema-instance";
+const WMS_1_1_1_DTD: &str = "http://schemas.opengis.net/wms/1.1.1/WMS_MS_Capabilities.dtd";
+const WMS_1_1_0_DTD: &str = "http://schemas.opengis.net/wms/1.1.0/capabilities_1_1_0.dtd";
+
+impl GetCapabilities {
+  /// Write the capabilities as a document of their `version`, which must be one of
+  /// `SUPPORTED_VERSIONS`
+  pub fn to_xml(&self) -> crate::Result<String> {
+    let dtd = match self.version.as_str() {
+      "1.3.0" => None,
+      "1.1.1" => Some(WMS_1_1_1_DTD),
+      "1.1.0" => Some(WMS_1_1_0_DTD),
+      v => {
+        return Err(Error::InvalidParameter(format!(
+          "Cannot write capabilities of version {:?}",
+          v
+        )))
+      }
+    };
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    if let Some(dtd) = dtd {
+      xml.push_str(&format!(
+        "<!DOCTYPE WMT_MS_Capabilities SYSTEM \"{}\">\n",
+        dtd
+      ));
+    }
+    let mut writer = Writer {
+      events: EmitterConfig::new()
+        .perform_indent(true)
+        .write_document_declaration(false)
+        .create_writer(Vec::new()),
+      v1_3: dtd.is_none(),
+    };
+    writer.capabilities(self)?;
+    let body =
+      String::from_utf8(writer.events.into_inner()).map_err(|e| Error::Xml(e.to_string()))?;
+    xml.push_str(&body);
+    Ok(xml)
+  }
+}
+
+/// Whether a SLD extension is used, which 1.3.0 declares in the SLD namespace
+fn uses_sld(capability: &Capability) -> bool {
+  let request = &capability.request;
+  request.describe_layer.is_some()
+    || request.get_legend_graphic.is_some()
+    || request.get_styles.is_some()
+    || capability.user_defined_symbolization.is_some()
+}
+
+fn xml_bool(value: bool) -> &'static str {
+  if value {
+    "1"
+  } else {
+    "0"
+  }
+}
+
+struct Writer {
+  events: EventWriter<Vec<u8>>,
+  /// Whether to write 1.3.0 rather than 1.1.x
+  v1_3: bool,
+}
+
+impl Writer {
+  fn start(&mut self, name: &str, attributes: &[(&str, &str)]) -> crate::Result<()> {
+    let mut element = XmlEvent::start_element(name);
+    for (name, value) in attributes {
+      element = element.attr(*name, value);
+    }
+    self.events.write(element)?;
+    Ok(())
+  }
+
+  fn end(&mut self) -> crate::Result<()> {
+    self.events.write(XmlEvent::end_element())?;
+    Ok(())
+  }
+
+  fn text(&mut self, name: &str, text: &str) -> crate::Result<()> {
+    self.start(name, &[])?;
+    self.events.write(XmlEvent::characters(text))?;
+    self.end()
+  }
+
+  fn optional_text(&mut self, name: &str, text: Option<&String>) -> crate::Result<()> {
+    match text {
+      Some(text) => self.text(name, text),
+      None => Ok(()),
+    }
+  }
+
+  /// An element whose text is left out when empty, as optional elements are parsed
+  fn non_empty_text(&mut self, name: &str, text: &str) -> crate::Result<()> {
+    if text.is_empty() {
+      Ok(())
+    } else {
+      self.text(name, text)
+    }
+  }
+
+  /// The name of an element of the SLD extension, in the SLD namespace for 1.3.0
+  fn sld(&self, name: &str) -> String {
+    if self.v1_3 {
+      format!("sld:{}", name)
+    } else {
+      name.to_string()
+    }
+  }
+
+  fn capabilities(&mut self, capa: &GetCapabilities) -> crate::Result<()> {
+    let name = if self.v1_3 {
+      "WMS_Capabilities"
+    } else {
+      "WMT_MS_Capabilities"
+    };
+    let mut root = XmlEvent::start_element(name).attr("version", &capa.version);
+    if let Some(sequence) = &capa.update_sequence {
+      root = root.attr("updateSequence", sequence);
+    }
+    if self.v1_3 {
+      root = root
+        .default_ns(WMS_NAMESPACE)
+        .ns("xlink", XLINK_NAMESPACE)
+        .ns("xsi", XSI_NAMESPACE)
+        .attr("xsi:schemaLocation", WMS_SCHEMA_LOCATION);
+      if uses_sld(&capa.capability) {
+        root = root.ns("sld", SLD_NAMESPACE);
+      }
+    }
+    self.events.write(root)?;
+    self.service(&capa.service)?;
+    self.capability(&capa.capability)?;
+    self.end()
+  }
+
+  fn service(&mut self, service: &Service) -> crate::Result<()> {
+    self.start("Service", &[])?;
+    self.text("Name", &service.name)?;
+    self.text("Title", &service.title)?;
+    self.non_empty_text("Abstract", &service.abstr)?;
+    self.keyword_list(&service.keyword_list)?;
+    self.online_resource(&service.online_resource)?;
+    if let Some(contact) = &service.contact_information {
+      self.contact_information(contact)?;
+    }
+    self.optional_text("Fees", service.fees.as_ref())?;
+    self.optional_text("AccessConstraints", service.access_constraints.as_ref())?;
+    if self.v1_3 {
+      for (name, value) in [
+        ("LayerLimit", service.layer_limit),
+        ("MaxWidth", service.max_width),
+        ("MaxHeight", service.max_height),
+      ] {
+        self.optional_text(name, value.map(|v| v.to_string()).as_ref())?;
+      }
+    }
+    self.end()
+  }
+
+  fn contact_information(&mut self, contact: &ContactInformation) -> crate::Result<()> {
+    self.start("ContactInformation", &[])?;
+    if let Some(person) = &contact.contact_person_primary {
+      self.start("ContactPersonPrimary", &[])?;
+      self.text("ContactPerson", &person.contact_person)?;
+      self.text("ContactOrganization", &person.contact_organization)?;
+      self.end()?;
+    }
+    self.optional_text("ContactPosition", contact.contact_position.as_ref())?;
+    if let Some(address) = &contact.contact_address {
+      self.start("ContactAddress", &[])?;
+      self.text("AddressType", &address.address_type)?;
+      self.text("Address", &address.address)?;
+      self.text("City", &address.city)?;
+      self.text("StateOrProvince", &address.state_or_province)?;
+      self.text("PostCode", &address.post_code)?;
+      self.text("Country", &address.country)?;
+      self.end()?;
+    }
+    self.optional_text(
+      "ContactVoiceTelephone",
+      contact.contact_voice_telephone.as_ref(),
+    )?;
+    self.optional_text(
+      "ContactFacsimileTelephone",
+      contact.contact_facsimile_telephone.as_ref(),
+    )?;
+    self.optional_text(
+      "ContactElectronicMailAddress",
+      contact.contact_electronic_mail_address.as_ref(),
+    )?;
+    self.end()
+  }
+
+  fn keyword_list(&mut self, keywords: &KeywordList) -> crate::Result<()> {
+    if keywords.keyword.is_empty() {
+      return Ok(());
+    }
+    self.start("KeywordList", &[])?;
+    for keyword in &keywords.keyword {
+      self.text("Keyword", keyword)?;
+    }
+    self.end()
+  }
+
+  /// The OnlineResource element, declaring the XLink namespace where it is not yet in scope
+  fn online_resource(&mut self, resource: &OnlineResource) -> crate::Result<()> {
+    self.events.write(
+      XmlEvent::start_element("OnlineResource")
+        .ns("xlink", XLINK_NAMESPACE)
+        .attr("xlink:type", "simple")
+        .attr("xlink:href", &resource.href),
+    )?;
+    self.end()
+  }
+
+  fn capability(&mut self, capability: &Capability) -> crate::Result<()> {
+    self.start("Capability", &[])?;
+    self.start("Request", &[])?;
+    let request = &capability.request;
+    self.operation("GetCapabilities", &request.get_capabilities)?;
+    self.operation("GetMap", &request.get_map)?;
+    if let Some(op) = &request.get_feature_info {
+      self.operation("GetFeatureInfo", op)?;
+    }
+    for (name, op) in [
+      ("DescribeLayer", &request.describe_layer),
+      ("GetLegendGraphic", &request.get_legend_graphic),
+      ("GetStyles", &request.get_styles),
+    ] {
+      if let Some(op) = op {
+        self.operation(&self.sld(name), op)?;
+      }
+    }
+    self.end()?;
+
+    self.start("Exception", &[])?;
+    for format in &capability.exception.formats {
+      self.text("Format", format)?;
+    }
+    self.end()?;
+    if let Some(symbolization) = &capability.user_defined_symbolization {
+      self.start(
+        &self.sld("UserDefinedSymbolization"),
+        &[
+          ("SupportSLD", xml_bool(symbolization.support_sld)),
+          ("UserLayer", xml_bool(symbolization.user_layer)),
+          ("UserStyle", xml_bool(symbolization.user_style)),
+          ("RemoteWFS", xml_bool(symbolization.remote_wfs)),
+        ],
+      )?;
+      self.end()?;
+    }
+    if let Some(layer) = &capability.layer {
+      self.layer(layer)?;
+    }
+    self.end()
+  }
+
+  fn operation(&mut self, name: &str, op: &Operation) -> crate::Result<()> {
+    self.start(name, &[])?;
+    for format in &op.formats {
+      self.text("Format", format)?;
+    }
+    for dcp in &op.dcp_type {
+      self.start("DCPType", &[])?;
+      self.start("HTTP", &[])?;
+      for (name, method) in [("Get", &dcp.http.get), ("Post", &dcp.http.post)] {
+        if let Some(method) = method {
+          self.start(name, &[])?;
+          self.online_resource(&method.online_resource)?;
+          self.end()?;
+        }
+      }
+      self.end()?;
+      self.end()?;
+    }
+    self.end()
+  }
+
+  fn layer(&mut self, layer: &Layer) -> crate::Result<()> {
+    // Declared attributes are written even when false or 0, as they replace inherited ones
+    let numbers: Vec<(&str, String)> = [
+      ("cascaded", layer.cascaded),
+      ("fixedWidth", layer.fixed_width),
+      ("fixedHeight", layer.fixed_height),
+    ]
+    .iter()
+    .filter_map(|(name, value)| value.map(|v| (*name, v.to_string())))
+    .collect();
+    let mut attributes = Vec::new();
+    for (name, value) in [
+      ("queryable", layer.queryable),
+      ("opaque", layer.opaque),
+      ("noSubsets", layer.no_subsets),
+    ] {
+      if let Some(value) = value {
+        attributes.push((name, xml_bool(value)));
+      }
+    }
+    for (name, value) in numbers.iter() {
+      attributes.push((name, value.as_str()));
+    }
+    self.start("Layer", &attributes)?;
+
+    self.non_empty_text("Name", &layer.name)?;
+    self.text("Title", &layer.title)?;
+    self.non_empty_text("Abstract", &layer.abstr)?;
+    self.keyword_list(&layer.keyword_list)?;
+    // Sorted, to write the same document every time
+    let (crs_name, crs) = if self.v1_3 {
+      ("CRS", &layer.crs)
+    } else {
+      ("SRS", &layer.srs)
+    };
+    let mut crs: Vec<&String> = crs.iter().collect();
+    crs.sort();
+    for crs in crs {
+      self.text(crs_name, crs)?;
+    }
+    if self.v1_3 {
+      if let Some(b) = &layer.ex_bbox {
+        self.start("EX_GeographicBoundingBox", &[])?;
+        self.text("westBoundLongitude", &b.west_bound_longitude.to_string())?;
+        self.text("eastBoundLongitude", &b.east_bound_longitude.to_string())?;
+        self.text("southBoundLatitude", &b.south_bound_latitude.to_string())?;
+        self.text("northBoundLatitude", &b.north_bound_latitude.to_string())?;
+        self.end()?;
+      }
+    } else if let Some(b) = &layer.ll_bbox {
+      self.start(
+        "LatLonBoundingBox",
+        &[
+          ("minx", &b.minx.to_string()),
+          ("miny", &b.miny.to_string()),
+          ("maxx", &b.maxx.to_string()),
+          ("maxy", &b.maxy.to_string()),
+        ],
+      )?;
+      self.end()?;
+    }
+    for bbox in &layer.bbox {
+      self.bounding_box(bbox)?;
+    }
+    for dimension in &layer.dimension {
+      self.dimension(dimension)?;
+    }
+    if !self.v1_3 {
+      for extent in &layer.extent {
+        let mut attributes = vec![("name", extent.name.as_str())];
+        if let Some(default) = &extent.default {
+          attributes.push(("default", default));
+        }
+        for (name, value) in [
+          ("multipleValues", extent.multiple_values),
+          ("nearestValue", extent.nearest_value),
+          ("current", extent.current),
+        ] {
+          if value {
+            attributes.push((name, xml_bool(value)));
+          }
+        }
+        self.start("Extent", &attributes)?;
+        self.events.write(XmlEvent::characters(&extent.value))?;
+        self.end()?;
+      }
+    }
+    if let Some(attribution) = &layer.attribution {
+      self.attribution(attribution)?;
+    }
+    for authority in &layer.authority_url {
+      self.start("AuthorityURL", &[("name", &authority.name)])?;
+      self.online_resource(&authority.online_resource)?;
+      self.end()?;
+    }
+    for identifier in &layer.identifiers {
+      self.start("Identifier", &[("authority", &identifier.authority)])?;
+      self.events.write(XmlEvent::characters(&identifier.value))?;
+      self.end()?;
+    }
+    for metadata in &layer.metadata_url {
+      self.start("MetadataURL", &[("type", &metadata.metadata_type)])?;
+      self.text("Format", &metadata.format)?;
+      self.online_resource(&metadata.online_resource)?;
+      self.end()?;
+    }
+    for url in &layer.data_url {
+      self.resource_url("DataURL", url)?;
+    }
+    for url in &layer.feature_list_url {
+      self.resource_url("FeatureListURL", url)?;
+    }
+    for style in &layer.styles {
+      self.style(style)?;
+    }
+    if self.v1_3 {
+      for (name, value) in [
+        ("MinScaleDenominator", layer.min_scale_denominator),
+        ("MaxScaleDenominator", layer.max_scale_denominator),
+      ] {
+        self.optional_text(name, value.map(|v| v.to_string()).as_ref())?;
+      }
+    } else if let Some(hint) = &layer.scale_hint {
+      self.start(
+        "ScaleHint",
+        &[
+          ("min", &hint.min.to_string()),
+          ("max", &hint.max.to_string()),
+        ],
+      )?;
+      self.end()?;
+    }
+    for child in &layer.layers {
+      self.layer(child)?;
+    }
+    self.end()
+  }
+
+  fn bounding_box(&mut self, bbox: &BoundingBox) -> crate::Result<()> {
+    let crs = if self.v1_3 { "CRS" } else { "SRS" };
+    let resx = bbox.resx.map(|r| r.to_string());
+    let resy = bbox.resy.map(|r| r.to_string());
+    let (minx, miny) = (bbox.minx.to_string(), bbox.miny.to_string());
+    let (maxx, maxy) = (bbox.maxx.to_string(), bbox.maxy.to_string());
+    let mut attributes = vec![
+      (crs, bbox.srs.as_str()),
+      ("minx", &minx),
+      ("miny", &miny),
+      ("maxx", &maxx),
+      ("maxy", &maxy),
+    ];
+    if let Some(resx) = &resx {
+      attributes.push(("resx", resx));
+    }
+    if let Some(resy) = &resy {
+      attributes.push(("resy", resy));
+    }
+    self.start("BoundingBox", &attributes)?;
+    self.end()
+  }
+
+  /// A Dimension element, which only carries the extent and its options in 1.3.0
+  fn dimension(&mut self, dimension: &Dimension) -> crate::Result<()> {
+    let mut attributes = vec![
+      ("name", dimension.name.as_str()),
+      ("units", &dimension.units),
+    ];
+    if let Some(symbol) = &dimension.unit_symbol {
+      attributes.push(("unitSymbol", symbol));
+    }
+    if self.v1_3 {
+      if let Some(default) = &dimension.default {
+        attributes.push(("default", default));
+      }
+      for (name, value) in [
+        ("multipleValues", dimension.multiple_values),
+        ("nearestValue", dimension.nearest_value),
+        ("current", dimension.current),
+      ] {
+        if value {
+          attributes.push((name, xml_bool(value)));
+        }
+      }
+    }
+    self.start("Dimension", &attributes)?;
+    if self.v1_3 {
+      self.events.write(XmlEvent::characters(&dimension.extent))?;
+    }
+    self.end()
+  }
+
+  fn attribution(&mut self, attribution: &Attribution) -> crate::Result<()> {
+    self.start("Attribution", &[])?;
+    self.optional_text("Title", attribution.title.as_ref())?;
+    if let Some(resource) = &attribution.online_resource {
+      self.online_resource(resource)?;
+    }
+    if let Some(logo) = &attribution.logo_url {
+      self.picture_url("LogoURL", logo)?;
+    }
+    self.end()
+  }
+
+  fn style(&mut self, style: &Style) -> crate::Result<()> {
+    self.start("Style", &[])?;
+    self.text("Name", &style.name)?;
+    self.text("Title", &style.title)?;
+    self.optional_text("Abstract", style.abstr.as_ref())?;
+    for legend in &style.legend_url {
+      self.picture_url("LegendURL", legend)?;
+    }
+    if let Some(url) = &style.style_sheet_url {
+      self.resource_url("StyleSheetURL", url)?;
+    }
+    if !self.v1_3 {
+      if let Some(url) = &style.style_url {
+        self.resource_url("StyleURL", url)?;
+      }
+    }
+    self.end()
+  }
+
+  /// A LegendURL or LogoURL, whose size is left out when unknown
+  fn picture_url(&mut self, name: &str, url: &LegendUrl) -> crate::Result<()> {
+    let (width, height) = (url.width.to_string(), url.height.to_string());
+    if url.width > 0 || url.height > 0 {
+      self.start(name, &[("width", &width), ("height", &height)])?;
+    } else {
+      self.start(name, &[])?;
+    }
+    self.text("Format", &url.format)?;
+    self.online_resource(&url.online_resource)?;
+    self.end()
+  }
+
+  fn resource_url(&mut self, name: &str, url: &ResourceUrl) -> crate::Result<()> {
+    self.start(name, &[])?;
+    self.text("Format", &url.format)?;
+    self.online_resource(&url.online_resource)?;
+    self.end()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::wms::tests::{example_capabilities, parse_capabilities};
+
+  #[tokio::test]
+  async fn test_round_trip_v1_3_0() {
+    let capa = example_capabilities("1.3.0", &[]).await;
+    let xml = capa.to_xml().unwrap();
+    assert!(xml.contains("<WMS_Capabilities xmlns=\"http://www.opengis.net/wms\""));
+    assert!(xml.contains("<CRS>CRS:84</CRS>"));
+    assert!(!xml.contains("<!DOCTYPE"));
+    assert_eq!(parse_capabilities(&xml).await, capa);
+  }
+
+  #[tokio::test]
+  async fn test_round_trip_v1_1_1() {
+    let capa = example_capabilities("1.1.1", &[]).await;
+    assert_eq!(capa.capability.exception.formats.len(), 5);
+    let symbolization = capa.capability.user_defined_symbolization.as_ref();
+    assert!(symbolization.unwrap().support_sld);
+    let xml = capa.to_xml().unwrap();
+    assert!(xml.contains("<!DOCTYPE WMT_MS_Capabilities SYSTEM"));
+    assert!(xml.contains("<WMT_MS_Capabilities version=\"1.1.1\" updateSequence=\"24609\">"));
+    assert!(xml.contains("<ScaleHint min=\"4000\" max=\"75000\" />"));
+    assert_eq!(parse_capabilities(&xml).await, capa);
+  }
+
+  #[tokio::test]
+  async fn test_rewrite_and_filter() {
+    let mut capa = example_capabilities("1.3.0", &[]).await;
+    capa.capability.request.get_map.dcp_type[0]
+      .http
+      .get
+      .as_mut()
+      .unwrap()
+      .online_resource
+      .href = "https://gateway/wms?a=1&b=2".to_string();
+    let root = capa.capability.layer.as_mut().unwrap();
+    root.layers.retain(|l| l.name != "ROADS_RIVERS");
+    let xml = capa.to_xml().unwrap();
+    assert!(xml.contains("xlink:href=\"https://gateway/wms?a=1&amp;b=2\""));
+    let republished = parse_capabilities(&xml).await;
+    assert_eq!(republished.layer("ROADS_RIVERS"), None);
+    assert_eq!(
+      republished.capability.request.get_map.get_url(),
+      Some("https://gateway/wms?a=1&b=2")
+    );
+
+    capa.version = "2.0.0".to_string();
+    assert!(capa.to_xml().is_err());
+  }
+}